@@ -1,12 +1,26 @@
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
+use std::fmt;
 
 use crate::environment_config::{AgeAndDeath, Education, Wealth};
 
-#[derive(Clone)]
+/// Identifier of an agent that stays the same for its whole life and is never
+/// reused, unlike its index in [`AgentStore`](crate::AgentStore).
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[serde(transparent)]
+pub struct AgentId(pub usize);
+
+impl fmt::Display for AgentId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Agent {
-    pub children: Vec<usize>,
-    pub id: usize,
+    pub children: Vec<AgentId>,
+    id: AgentId, // fixed, as the store indexes agents by it
     pub x: f64,
     pub y: f64,
     pub wealth: f64,
@@ -15,11 +29,13 @@ pub struct Agent {
     pub alive: bool,
     pub mid_age: f64,   // age where death chance is 50%
     pub steepness: f64, // how quickly death probability rises with age
+    pub inherited: f64, // wealth received from estates over the agent's life
 }
 
 impl Agent {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        id: usize,
+        id: AgentId,
         min_x: f64,
         max_x: f64,
         min_y: f64,
@@ -27,8 +43,8 @@ impl Agent {
         age_and_death: &AgeAndDeath,
         education: &Education,
         wealth: &Wealth,
+        rng: &mut impl Rng,
     ) -> Self {
-        let mut rng = rand::thread_rng();
         let age_years = rng.gen_range(0.0..age_and_death.max_start_age);
 
         let age_months = (age_years * 12.0).floor() as u32;
@@ -50,14 +66,37 @@ impl Agent {
             x: rng.gen_range(min_x..max_x),
             y: rng.gen_range(min_y..max_y),
             wealth: rng.gen_range(wealth.min_initial_wealth..wealth.max_initial_wealth),
-            education: education,
+            education,
             age: age_months,
             alive: true,
             mid_age: age_and_death.mid_age,
             steepness: age_and_death.steepness,
+            inherited: 0.0,
         }
     }
 
+    /// An agent of age 0 at (`x`, `y`) without wealth, education or children,
+    /// dying as described by `mid_age` and `steepness`.
+    pub fn newborn(id: AgentId, x: f64, y: f64, mid_age: f64, steepness: f64) -> Self {
+        Self {
+            id,
+            children: Vec::new(),
+            x,
+            y,
+            wealth: 0.0,
+            education: 0.0,
+            age: 0,
+            alive: true,
+            mid_age,
+            steepness,
+            inherited: 0.0,
+        }
+    }
+
+    pub fn id(&self) -> AgentId {
+        self.id
+    }
+
     pub fn move_randomly(
         &mut self,
         max_distance: f64,
@@ -65,8 +104,8 @@ impl Agent {
         min_y: f64,
         max_x: f64,
         max_y: f64,
+        rng: &mut impl Rng,
     ) {
-        let mut rng = rand::thread_rng();
         let theta = rng.gen_range(0.0..2.0 * PI);
         let delta = rng.gen_range(0.0..max_distance);
         // Ensure the agent stays within bounds
@@ -78,12 +117,12 @@ impl Agent {
         alpha * self.education + beta * self.age as f64
     }
 
-    pub fn age_and_check_death(&mut self) -> bool {
+    pub fn age_and_check_death(&mut self, rng: &mut impl Rng) -> bool {
         self.age += 1;
         let mid_age = self.mid_age * 12.0; // age where death chance is 50%
         let steepness = self.steepness; // how quickly probability rises with age
         let death_chance = 1.0 / (1.0 + (-steepness * (self.age as f64 - mid_age)).exp());
-        if rand::random::<f64>() < death_chance {
+        if rng.gen::<f64>() < death_chance {
             self.alive = false;
         }
         !self.alive
@@ -0,0 +1,217 @@
+use rayon::prelude::*;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::ops::{Deref, Index, IndexMut};
+
+use crate::agent::{Agent, AgentId};
+
+/// The agents of an environment with O(1) lookup by [`AgentId`]. Derefs to the
+/// slice of agents, whose order and indices change when dead agents are removed;
+/// links between agents therefore always use ids. Agents can be changed in place
+/// through indexing and `iter_mut`, but not reordered.
+pub struct AgentStore {
+    agents: Vec<Agent>,
+    index: HashMap<AgentId, usize>, // id to position in `agents`
+    next_id: AgentId,
+}
+
+impl AgentStore {
+    /// Stores `agents`. New ids continue after the largest one.
+    ///
+    /// # Panics
+    ///
+    /// If two agents share an id.
+    pub fn new(agents: Vec<Agent>) -> Self {
+        let next_id = AgentId(agents.iter().map(|a| a.id().0 + 1).max().unwrap_or(0));
+        Self::with_next_id(agents, next_id)
+            .unwrap_or_else(|id| panic!("Agent id {} is not unique", id))
+    }
+
+    /// Fails with the first id that is not unique.
+    fn with_next_id(agents: Vec<Agent>, next_id: AgentId) -> Result<Self, AgentId> {
+        let mut index = HashMap::with_capacity(agents.len());
+        for (i, agent) in agents.iter().enumerate() {
+            if index.insert(agent.id(), i).is_some() {
+                return Err(agent.id());
+            }
+        }
+        Ok(Self {
+            agents,
+            index,
+            next_id,
+        })
+    }
+
+    fn reindex(&mut self) {
+        self.index = self
+            .agents
+            .iter()
+            .enumerate()
+            .map(|(i, a)| (a.id(), i))
+            .collect();
+    }
+
+    /// Reserves the id of an agent yet to be added.
+    pub fn next_id(&mut self) -> AgentId {
+        let id = self.next_id;
+        self.next_id.0 += 1;
+        id
+    }
+
+    /// Adds an agent whose id came from [`AgentStore::next_id`].
+    pub fn push(&mut self, agent: Agent) {
+        self.index.insert(agent.id(), self.agents.len());
+        self.agents.push(agent);
+    }
+
+    pub fn index_of(&self, id: AgentId) -> Option<usize> {
+        self.index.get(&id).copied()
+    }
+
+    pub fn get(&self, id: AgentId) -> Option<&Agent> {
+        self.index_of(id).map(|i| &self.agents[i])
+    }
+
+    pub fn get_mut(&mut self, id: AgentId) -> Option<&mut Agent> {
+        self.index_of(id).map(|i| &mut self.agents[i])
+    }
+
+    /// The agents at indices `a` and `b`, which must differ.
+    pub fn pair_mut(&mut self, a: usize, b: usize) -> (&mut Agent, &mut Agent) {
+        assert_ne!(a, b, "Cannot borrow one agent twice");
+        if a < b {
+            let (left, right) = self.agents.split_at_mut(b);
+            (&mut left[a], &mut right[0])
+        } else {
+            let (left, right) = self.agents.split_at_mut(a);
+            (&mut right[0], &mut left[b])
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Agent> {
+        self.agents.iter_mut()
+    }
+
+    /// Removes dead agents. Their ids are not reused.
+    pub fn remove_dead(&mut self) {
+        self.agents.retain(|a| a.alive);
+        self.reindex();
+    }
+}
+
+impl Deref for AgentStore {
+    type Target = [Agent];
+
+    fn deref(&self) -> &[Agent] {
+        &self.agents
+    }
+}
+
+impl Index<usize> for AgentStore {
+    type Output = Agent;
+
+    fn index(&self, index: usize) -> &Agent {
+        &self.agents[index]
+    }
+}
+
+impl IndexMut<usize> for AgentStore {
+    fn index_mut(&mut self, index: usize) -> &mut Agent {
+        &mut self.agents[index]
+    }
+}
+
+impl<'a> IntoIterator for &'a AgentStore {
+    type Item = &'a Agent;
+    type IntoIter = std::slice::Iter<'a, Agent>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.agents.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut AgentStore {
+    type Item = &'a mut Agent;
+    type IntoIter = std::slice::IterMut<'a, Agent>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.agents.iter_mut()
+    }
+}
+
+impl<'a> IntoParallelIterator for &'a mut AgentStore {
+    type Item = &'a mut Agent;
+    type Iter = rayon::slice::IterMut<'a, Agent>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        self.agents.par_iter_mut()
+    }
+}
+
+impl Serialize for AgentStore {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (&self.agents, self.next_id).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for AgentStore {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (agents, next_id): (Vec<Agent>, AgentId) = Deserialize::deserialize(deserializer)?;
+        if let Some(agent) = agents.iter().find(|a| a.id() >= next_id) {
+            return Err(D::Error::custom(format!(
+                "agent id {} is not below the next id {}",
+                agent.id(),
+                next_id
+            )));
+        }
+        Self::with_next_id(agents, next_id)
+            .map_err(|id| D::Error::custom(format!("agent id {} is not unique", id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_json(ids: &[usize], next_id: usize) -> String {
+        let agents: Vec<Agent> = ids
+            .iter()
+            .map(|&id| Agent::newborn(AgentId(id), 0.0, 0.0, 80.0, 0.1))
+            .collect();
+        serde_json::to_string(&(agents, AgentId(next_id))).unwrap()
+    }
+
+    #[test]
+    fn deserializes_and_indexes_by_id() {
+        let store: AgentStore = serde_json::from_str(&store_json(&[4, 2, 7], 8)).unwrap();
+        assert_eq!(store.index_of(AgentId(7)), Some(2));
+        assert_eq!(store.get(AgentId(2)).map(Agent::id), Some(AgentId(2)));
+    }
+
+    #[test]
+    fn duplicate_ids_are_a_deserialization_error() {
+        let error = serde_json::from_str::<AgentStore>(&store_json(&[1, 3, 1], 4))
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("agent id 1 is not unique"));
+    }
+
+    #[test]
+    fn ids_from_the_next_id_on_are_a_deserialization_error() {
+        let error = serde_json::from_str::<AgentStore>(&store_json(&[1, 5], 5))
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("not below the next id 5"));
+    }
+
+    #[test]
+    fn pair_mut_returns_the_agents_in_argument_order() {
+        let mut store = AgentStore::new(vec![
+            Agent::newborn(AgentId(0), 0.0, 0.0, 80.0, 0.1),
+            Agent::newborn(AgentId(1), 0.0, 0.0, 80.0, 0.1),
+        ]);
+        let (a, b) = store.pair_mut(1, 0);
+        assert_eq!((a.id(), b.id()), (AgentId(1), AgentId(0)));
+    }
+}
@@ -0,0 +1,134 @@
+use rayon::prelude::*;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+
+use crate::config_error::{ConfigError, ValidationIssue};
+use crate::environment::Environment;
+use crate::environment_config::EnvironmentConfig;
+use crate::metrics::Stats;
+
+/// A config field searched or sampled within `[min, max]`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ParameterRange {
+    pub path: String,
+    pub min: f64,
+    pub max: f64,
+    #[serde(default)]
+    pub integer: bool, // round values, for fields such as `num_agents`
+}
+
+/// Metrics columns to read from a headless run, averaged over its last iterations.
+#[derive(Clone, Debug)]
+pub struct OutputWindow {
+    pub columns: Vec<String>,
+    pub window: usize, // number of final iterations averaged
+}
+
+impl OutputWindow {
+    pub fn new(columns: Vec<String>, window: usize) -> Result<Self, ConfigError> {
+        let issues: Vec<ValidationIssue> = columns
+            .iter()
+            .filter(|c| !Stats::COLUMN_NAMES.contains(&c.as_str()))
+            .map(|c| ValidationIssue {
+                path: format!("outputs.{}", c),
+                constraint: format!("must be one of {}", Stats::COLUMN_NAMES.join(", ")),
+            })
+            .collect();
+        if !issues.is_empty() {
+            return Err(ConfigError::Invalid(issues));
+        }
+        Ok(Self {
+            columns,
+            window: window.max(1),
+        })
+    }
+
+    /// Runs `config` to completion and returns the averages of the selected
+    /// columns over the last `window` iterations, in the order of `columns`.
+    /// Iterations without living agents are skipped; NaN if none remain.
+    pub fn evaluate(&self, config: &EnvironmentConfig) -> Result<Vec<f64>, ConfigError> {
+        let mut env = Environment::try_new(config)?;
+        let mut recent = VecDeque::with_capacity(self.window);
+        while !env.finished() {
+            env.step()?;
+            if recent.len() == self.window {
+                recent.pop_front();
+            }
+            recent.extend(env.stats());
+        }
+
+        Ok(self
+            .columns
+            .iter()
+            .map(|column| {
+                let values: Vec<f64> = recent.iter().filter_map(|s| s.column(column)).collect();
+                values.iter().sum::<f64>() / values.len() as f64
+            })
+            .collect())
+    }
+
+    /// Evaluates every config in parallel, keeping the input order. Fails with
+    /// the error of every config that could not be run, under `runs.<index>`.
+    pub fn evaluate_all(
+        &self,
+        configs: &[EnvironmentConfig],
+    ) -> Result<Vec<Vec<f64>>, ConfigError> {
+        let results: Vec<_> = configs.par_iter().map(|c| self.evaluate(c)).collect();
+        let issues: Vec<ValidationIssue> = results
+            .iter()
+            .enumerate()
+            .filter_map(|(i, result)| {
+                result.as_ref().err().map(|e| ValidationIssue {
+                    path: format!("runs.{}", i),
+                    constraint: e.to_string(),
+                })
+            })
+            .collect();
+        if !issues.is_empty() {
+            return Err(ConfigError::Invalid(issues));
+        }
+        results.into_iter().collect()
+    }
+}
+
+impl ParameterRange {
+    /// Maps `unit` in `[0, 1]` onto the range, rounding integer parameters.
+    pub fn scale(&self, unit: f64) -> f64 {
+        let value = self.min + unit * (self.max - self.min);
+        if self.integer {
+            value.round()
+        } else {
+            value
+        }
+    }
+
+    /// Checks `min < max`, reporting issues under `parameters.<index>`.
+    pub(crate) fn validate(&self, index: usize, issues: &mut Vec<ValidationIssue>) {
+        if !(self.min.is_finite() && self.max.is_finite() && self.min < self.max) {
+            issues.push(ValidationIssue {
+                path: format!("parameters.{}.min", index),
+                constraint: "must be finite and less than max".to_string(),
+            });
+        }
+    }
+}
+
+/// Sets every parameter to its value on a copy of `base` and validates the result.
+pub fn apply_parameters(
+    base: &EnvironmentConfig,
+    parameters: &[ParameterRange],
+    values: &[f64],
+) -> Result<EnvironmentConfig, ConfigError> {
+    let mut config = base.clone();
+    for (p, value) in parameters.iter().zip(values) {
+        let value = if p.integer {
+            Value::from(*value as i64)
+        } else {
+            Value::from(*value)
+        };
+        config.set_path(&p.path, value)?;
+    }
+    config.validate()?;
+    Ok(config)
+}
@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::config_error::{ConfigError, ValidationIssue};
+use crate::environment::Environment;
+use crate::metrics::Stats;
+use crate::replicates::Trajectory;
+use crate::schedule::{changeable_during_run, read_at_creation};
+
+/// Name of the branch that continues a checkpoint unchanged.
+pub const BASELINE: &str = "baseline";
+
+/// A counterfactual continuation of a checkpoint with some config fields changed.
+#[derive(Clone, Debug)]
+pub struct Branch {
+    pub name: String,
+    pub assignments: Vec<String>, // `path=value`, as for `--set`
+}
+
+impl Branch {
+    /// The branch without overrides every other branch is compared against.
+    pub fn baseline() -> Self {
+        Self {
+            name: BASELINE.to_string(),
+            assignments: Vec::new(),
+        }
+    }
+
+    /// Parses `name:path=value;path=value`, e.g. `high_tax:transaction.tax_rate=0.2`.
+    pub fn parse(spec: &str) -> Result<Self, ConfigError> {
+        let invalid = |message: &str| ConfigError::Override {
+            path: spec.to_string(),
+            message: message.to_string(),
+        };
+        let (name, assignments) = spec
+            .split_once(':')
+            .ok_or_else(|| invalid("expected <name>:<path>=<value>;..."))?;
+        let name = name.trim();
+        if name.is_empty() || name == BASELINE || name.contains(',') {
+            return Err(invalid(
+                "branch names must be non-empty, without commas and not `baseline`",
+            ));
+        }
+        let assignments: Vec<String> = assignments
+            .split(';')
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+            .map(str::to_string)
+            .collect();
+        if assignments.is_empty() {
+            return Err(invalid("no overrides given"));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            assignments,
+        })
+    }
+
+    /// Applies the overrides to a restored environment. Fields that are only
+    /// read at start-up cannot be changed, apart from `num_iterations` and
+    /// `schedule`, and neither can those only read when agents are created. New
+    /// schedule entries must not start before the checkpoint iteration, as they
+    /// would never take effect.
+    pub fn apply(&self, env: &mut Environment) -> Result<(), ConfigError> {
+        let mut config = env.config().clone();
+        for assignment in &self.assignments {
+            let path = assignment.split_once('=').map_or("", |(p, _)| p.trim());
+            if !(changeable_during_run(path) || path == "num_iterations" || path == "schedule") {
+                return Err(ConfigError::Override {
+                    path: path.to_string(),
+                    message: "cannot change after the checkpoint".to_string(),
+                });
+            }
+            if read_at_creation(path) {
+                return Err(ConfigError::Override {
+                    path: path.to_string(),
+                    message: "is only read when agents are created, so changing it after the checkpoint has no effect".to_string(),
+                });
+            }
+            config.apply_assignment(assignment)?;
+        }
+        let issues: Vec<ValidationIssue> = config
+            .schedule
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| {
+                entry.at < env.iteration() && !env.config().schedule.contains(entry)
+            })
+            .map(|(i, _)| ValidationIssue {
+                path: format!("schedule.{}.at", i),
+                constraint: format!(
+                    "must not be before the checkpoint iteration {}",
+                    env.iteration()
+                ),
+            })
+            .collect();
+        if !issues.is_empty() {
+            return Err(ConfigError::Invalid(issues));
+        }
+        config.validate()?;
+        env.config = config;
+        Ok(())
+    }
+}
+
+/// Writes the metrics of every branch side by side, one row per iteration: for
+/// each column its baseline value, then each other branch's value and its
+/// difference to the baseline. `trajectories[0]` must be the baseline.
+pub fn write_differences(
+    path: &str,
+    names: &[String],
+    trajectories: &[Trajectory],
+) -> io::Result<()> {
+    let mut by_iteration: BTreeMap<usize, Vec<Option<&Stats>>> = BTreeMap::new();
+    for (b, trajectory) in trajectories.iter().enumerate() {
+        for stats in trajectory {
+            by_iteration
+                .entry(stats.iteration)
+                .or_insert_with(|| vec![None; trajectories.len()])[b] = Some(stats);
+        }
+    }
+
+    let mut file = BufWriter::new(File::create(path)?);
+    let mut header = String::from("iteration");
+    for column in Stats::COLUMN_NAMES {
+        header.push_str(&format!(",{}_{}", column, names[0]));
+        for name in &names[1..] {
+            header.push_str(&format!(",{}_{},{}_{}_diff", column, name, column, name));
+        }
+    }
+    writeln!(file, "{}", header)?;
+
+    let format = |value: Option<f64>| value.map_or(String::new(), |v| format!("{:.5}", v));
+    for (iteration, row) in by_iteration {
+        write!(file, "{}", iteration)?;
+        let values: Vec<_> = row.iter().map(|s| s.map(Stats::values)).collect();
+        for c in 0..Stats::COLUMN_NAMES.len() {
+            let baseline = values[0].map(|v| v[c]);
+            write!(file, ",{}", format(baseline))?;
+            for branch in &values[1..] {
+                let value = branch.map(|v| v[c]);
+                let difference = value.zip(baseline).map(|(v, b)| v - b);
+                write!(file, ",{},{}", format(value), format(difference))?;
+            }
+        }
+        writeln!(file)?;
+    }
+    file.flush()
+}
@@ -0,0 +1,527 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::batch::{apply_parameters, OutputWindow, ParameterRange};
+use crate::config_error::{ConfigError, ValidationIssue};
+use crate::config_file;
+use crate::environment_config::EnvironmentConfig;
+use crate::metrics::mean_and_std;
+use crate::sensitivity::latin_hypercube;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum CalibrationMethod {
+    /// Nelder–Mead simplex search from the middle of the parameter ranges.
+    NelderMead,
+    /// Rejection ABC: keeps the closest fraction of a Latin hypercube sample.
+    Abc,
+}
+
+/// An empirical value a metrics column should reach.
+#[derive(Deserialize, Clone, Debug)]
+pub struct CalibrationTarget {
+    pub output: String,
+    pub value: f64,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+}
+
+/// Calibration spec, read from a JSON, TOML or YAML file.
+#[derive(Deserialize, Clone, Debug)]
+pub struct CalibrationSpec {
+    pub method: CalibrationMethod,
+    pub targets: Vec<CalibrationTarget>,
+    pub parameters: Vec<ParameterRange>,
+    /// Number of final iterations the outputs are averaged over.
+    #[serde(default = "default_window")]
+    pub window: usize,
+    /// Seed of the ABC sample.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Simulation budget of Nelder–Mead.
+    #[serde(default = "default_max_evaluations")]
+    pub max_evaluations: usize,
+    /// Nelder–Mead stops once the errors across the simplex differ by less than this.
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+    /// Number of ABC simulations.
+    #[serde(default = "default_samples")]
+    pub samples: usize,
+    /// Fraction of the ABC simulations accepted into the posterior.
+    #[serde(default = "default_accept")]
+    pub accept: f64,
+}
+
+/// One simulated parameter set.
+#[derive(Clone, Debug)]
+pub struct Evaluation {
+    pub values: Vec<f64>,  // parameter values
+    pub outputs: Vec<f64>, // in the order of `CalibrationSpec::targets`
+    pub error: f64,
+}
+
+/// Every simulated parameter set and the best fit among them.
+pub struct CalibrationResult {
+    pub spec: CalibrationSpec,
+    pub evaluations: Vec<Evaluation>,
+    pub best: usize,
+    pub accepted: Vec<usize>, // ABC posterior sample; only the best for Nelder–Mead
+    pub config: EnvironmentConfig, // `base` with the best parameters applied
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_window() -> usize {
+    120
+}
+
+fn default_max_evaluations() -> usize {
+    100
+}
+
+fn default_tolerance() -> f64 {
+    1e-4
+}
+
+fn default_samples() -> usize {
+    200
+}
+
+fn default_accept() -> f64 {
+    0.1
+}
+
+/// Runs parameter sets given in the unit hypercube and keeps every evaluation.
+struct Evaluator<'a> {
+    spec: &'a CalibrationSpec,
+    base: EnvironmentConfig,
+    output_window: OutputWindow,
+    evaluations: Vec<Evaluation>,
+}
+
+impl CalibrationSpec {
+    pub fn load_from_file(path: &str) -> Result<Self, ConfigError> {
+        let spec: Self = config_file::read_document(Path::new(path))?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut issues = Vec::new();
+        let mut check = |ok: bool, path: String, constraint: &str| {
+            if !ok {
+                issues.push(ValidationIssue {
+                    path,
+                    constraint: constraint.to_string(),
+                });
+            }
+        };
+        check(
+            !self.targets.is_empty(),
+            "targets".to_string(),
+            "must not be empty",
+        );
+        for (i, target) in self.targets.iter().enumerate() {
+            check(
+                target.value.is_finite(),
+                format!("targets.{}.value", i),
+                "must be finite",
+            );
+            check(
+                target.weight.is_finite() && target.weight >= 0.0,
+                format!("targets.{}.weight", i),
+                "must be non-negative",
+            );
+        }
+        check(
+            !self.parameters.is_empty(),
+            "parameters".to_string(),
+            "must not be empty",
+        );
+        match self.method {
+            CalibrationMethod::NelderMead => {
+                check(
+                    self.max_evaluations > self.parameters.len(),
+                    "max_evaluations".to_string(),
+                    "must exceed the number of parameters",
+                );
+                check(
+                    self.tolerance >= 0.0,
+                    "tolerance".to_string(),
+                    "must be non-negative",
+                );
+            }
+            CalibrationMethod::Abc => {
+                check(
+                    self.samples >= 1,
+                    "samples".to_string(),
+                    "must be at least 1",
+                );
+                check(
+                    self.accept > 0.0 && self.accept <= 1.0,
+                    "accept".to_string(),
+                    "must be in (0, 1]",
+                );
+            }
+        }
+        for (i, p) in self.parameters.iter().enumerate() {
+            p.validate(i, &mut issues);
+        }
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(issues))
+        }
+    }
+
+    /// Weighted sum of squared relative errors of `outputs` against the targets.
+    /// Targets of 0 use the absolute error; runs without living agents score infinity.
+    pub fn error(&self, outputs: &[f64]) -> f64 {
+        let error: f64 = self
+            .targets
+            .iter()
+            .zip(outputs)
+            .map(|(target, output)| {
+                let scale = if target.value == 0.0 {
+                    1.0
+                } else {
+                    target.value.abs()
+                };
+                target.weight * ((output - target.value) / scale).powi(2)
+            })
+            .sum();
+        if error.is_nan() {
+            f64::INFINITY
+        } else {
+            error
+        }
+    }
+
+    /// Searches the parameters on top of `base`, running simulations headless in
+    /// parallel where the method allows. All runs share the simulation seed of
+    /// `base`, so the error surface is deterministic.
+    pub fn run(&self, base: &EnvironmentConfig) -> Result<CalibrationResult, ConfigError> {
+        let outputs = self.targets.iter().map(|t| t.output.clone()).collect();
+        let mut rng = match self.seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy(),
+        };
+        let mut base = base.clone();
+        base.seed.get_or_insert_with(|| rng.gen::<u32>() as u64);
+        let mut evaluator = Evaluator {
+            spec: self,
+            base,
+            output_window: OutputWindow::new(outputs, self.window)?,
+            evaluations: Vec::new(),
+        };
+
+        match self.method {
+            CalibrationMethod::NelderMead => {
+                nelder_mead(
+                    self.parameters.len(),
+                    self.max_evaluations,
+                    self.tolerance,
+                    |points| evaluator.evaluate(points),
+                )?;
+            }
+            CalibrationMethod::Abc => {
+                let points = latin_hypercube(self.samples, self.parameters.len(), &mut rng);
+                evaluator.evaluate(&points)?;
+            }
+        }
+
+        let Evaluator {
+            base, evaluations, ..
+        } = evaluator;
+        let accepted = self.accepted(&evaluations);
+        let best = accepted[0];
+        let config = apply_parameters(&base, &self.parameters, &evaluations[best].values)?;
+        Ok(CalibrationResult {
+            spec: self.clone(),
+            evaluations,
+            best,
+            accepted,
+            config,
+        })
+    }
+
+    /// Evaluations kept by the method, best first: the closest `accept` fraction
+    /// (at least one) for ABC, only the best for Nelder–Mead.
+    fn accepted(&self, evaluations: &[Evaluation]) -> Vec<usize> {
+        let mut ranked: Vec<usize> = (0..evaluations.len()).collect();
+        ranked.sort_by(|&a, &b| evaluations[a].error.total_cmp(&evaluations[b].error));
+        let count = match self.method {
+            CalibrationMethod::NelderMead => 1,
+            CalibrationMethod::Abc => (self.accept * evaluations.len() as f64).ceil() as usize,
+        };
+        ranked.truncate(count.max(1));
+        ranked
+    }
+}
+
+/// Minimises `objective` over the `dimensions`-dimensional unit hypercube, clamping
+/// points to its bounds, and returns the best point with its value. `objective`
+/// receives batches of points so that they can be evaluated in parallel, and is
+/// asked for at most `max_evaluations` points in total, which must exceed
+/// `dimensions`.
+fn nelder_mead(
+    dimensions: usize,
+    max_evaluations: usize,
+    tolerance: f64,
+    mut objective: impl FnMut(&[Vec<f64>]) -> Result<Vec<f64>, ConfigError>,
+) -> Result<(Vec<f64>, f64), ConfigError> {
+    let k = dimensions;
+    let clamp = |x: Vec<f64>| -> Vec<f64> { x.into_iter().map(|v| v.clamp(0.0, 1.0)).collect() };
+    let towards = |from: &[f64], to: &[f64], t: f64| -> Vec<f64> {
+        clamp(from.iter().zip(to).map(|(a, b)| a + t * (b - a)).collect())
+    };
+    // Evaluates as many leading points as the budget has left
+    let mut used = 0;
+    let mut evaluate = |points: &[Vec<f64>]| -> Result<Vec<f64>, ConfigError> {
+        let count = points.len().min(max_evaluations - used);
+        used += count;
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        objective(&points[..count])
+    };
+
+    let mut simplex = vec![vec![0.5; k]];
+    for i in 0..k {
+        let mut vertex = vec![0.5; k];
+        vertex[i] = 0.75;
+        simplex.push(vertex);
+    }
+    let mut errors = evaluate(&simplex)?;
+
+    loop {
+        let mut order: Vec<usize> = (0..=k).collect();
+        order.sort_by(|&a, &b| errors[a].total_cmp(&errors[b]));
+        simplex = order.iter().map(|&i| simplex[i].clone()).collect();
+        errors = order.iter().map(|&i| errors[i]).collect();
+        if errors[k] - errors[0] < tolerance {
+            break;
+        }
+
+        let centroid: Vec<f64> = (0..k)
+            .map(|d| simplex[..k].iter().map(|v| v[d]).sum::<f64>() / k as f64)
+            .collect();
+        let worst = simplex[k].clone();
+        let reflected = towards(&worst, &centroid, 2.0);
+        let Some(&reflected_error) = evaluate(std::slice::from_ref(&reflected))?.first() else {
+            break;
+        };
+
+        if reflected_error < errors[0] {
+            let expanded = towards(&worst, &centroid, 3.0);
+            (simplex[k], errors[k]) = match evaluate(std::slice::from_ref(&expanded))?.first() {
+                Some(&expanded_error) if expanded_error < reflected_error => {
+                    (expanded, expanded_error)
+                }
+                _ => (reflected, reflected_error),
+            };
+        } else if reflected_error < errors[k - 1] {
+            (simplex[k], errors[k]) = (reflected, reflected_error);
+        } else {
+            let contracted = towards(&worst, &centroid, 0.5);
+            match evaluate(std::slice::from_ref(&contracted))?.first() {
+                None => break,
+                Some(&contracted_error) if contracted_error < errors[k] => {
+                    (simplex[k], errors[k]) = (contracted, contracted_error);
+                }
+                Some(_) => {
+                    // Shrink every vertex halfway towards the best one, as far as
+                    // the budget allows
+                    let shrunk: Vec<Vec<f64>> = simplex[1..]
+                        .iter()
+                        .map(|v| towards(&simplex[0], v, 0.5))
+                        .collect();
+                    let shrunk_errors = evaluate(&shrunk)?;
+                    let count = shrunk_errors.len();
+                    simplex.splice(1..=count, shrunk.into_iter().take(count));
+                    errors.splice(1..=count, shrunk_errors);
+                }
+            }
+        }
+    }
+    let best = (0..=k)
+        .min_by(|&a, &b| errors[a].total_cmp(&errors[b]))
+        .unwrap_or(0);
+    Ok((simplex.swap_remove(best), errors[best]))
+}
+
+impl Evaluator<'_> {
+    /// Simulates the points in parallel and returns their errors.
+    fn evaluate(&mut self, unit_points: &[Vec<f64>]) -> Result<Vec<f64>, ConfigError> {
+        let values: Vec<Vec<f64>> = unit_points
+            .iter()
+            .map(|unit| {
+                self.spec
+                    .parameters
+                    .iter()
+                    .zip(unit)
+                    .map(|(p, u)| p.scale(*u))
+                    .collect()
+            })
+            .collect();
+        let configs = values
+            .iter()
+            .map(|v| apply_parameters(&self.base, &self.spec.parameters, v))
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+        let outputs = self.output_window.evaluate_all(&configs)?;
+
+        let mut errors = Vec::with_capacity(values.len());
+        for (values, outputs) in values.into_iter().zip(outputs) {
+            let error = self.spec.error(&outputs);
+            errors.push(error);
+            self.evaluations.push(Evaluation {
+                values,
+                outputs,
+                error,
+            });
+        }
+        Ok(errors)
+    }
+}
+
+impl CalibrationResult {
+    /// Mean and standard deviation of every parameter over the accepted evaluations.
+    pub fn posterior(&self) -> Vec<(f64, f64)> {
+        (0..self.spec.parameters.len())
+            .map(|i| {
+                let values: Vec<f64> = self
+                    .accepted
+                    .iter()
+                    .map(|&e| self.evaluations[e].values[i])
+                    .collect();
+                mean_and_std(&values)
+            })
+            .collect()
+    }
+
+    /// One row per simulation with its parameters, outputs, error and whether it
+    /// was accepted.
+    pub fn write_evaluations(&self, path: &str) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let mut header = vec!["run_id".to_string()];
+        header.extend(self.spec.parameters.iter().map(|p| p.path.clone()));
+        header.extend(self.spec.targets.iter().map(|t| t.output.clone()));
+        header.extend(["error".to_string(), "accepted".to_string()]);
+        writeln!(file, "{}", header.join(","))?;
+        for (run_id, evaluation) in self.evaluations.iter().enumerate() {
+            write!(file, "{}", run_id)?;
+            for value in evaluation.values.iter().chain(&evaluation.outputs) {
+                write!(file, ",{}", value)?;
+            }
+            writeln!(
+                file,
+                ",{:.6},{}",
+                evaluation.error,
+                self.accepted.contains(&run_id)
+            )?;
+        }
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(method: CalibrationMethod, accept: f64) -> CalibrationSpec {
+        CalibrationSpec {
+            method,
+            targets: Vec::new(),
+            parameters: Vec::new(),
+            window: default_window(),
+            seed: None,
+            max_evaluations: default_max_evaluations(),
+            tolerance: default_tolerance(),
+            samples: default_samples(),
+            accept,
+        }
+    }
+
+    fn evaluations(errors: &[f64]) -> Vec<Evaluation> {
+        errors
+            .iter()
+            .map(|&error| Evaluation {
+                values: Vec::new(),
+                outputs: Vec::new(),
+                error,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nelder_mead_converges_on_a_quadratic_within_its_budget() {
+        let minimum = [0.2, 0.7, 0.4];
+        let mut asked = 0;
+        let (best, value) = nelder_mead(3, 500, 1e-12, |points| {
+            asked += points.len();
+            Ok(points
+                .iter()
+                .map(|p| p.iter().zip(minimum).map(|(x, m)| (x - m).powi(2)).sum())
+                .collect())
+        })
+        .unwrap();
+        assert!(asked <= 500);
+        assert!(value < 1e-8, "value {}", value);
+        for (x, m) in best.iter().zip(minimum) {
+            assert!((x - m).abs() < 1e-3, "best {:?}", best);
+        }
+    }
+
+    #[test]
+    fn nelder_mead_never_exceeds_max_evaluations() {
+        // Budgets that run out in the middle of a shrink
+        for max_evaluations in 4..30 {
+            let mut asked = 0;
+            nelder_mead(3, max_evaluations, 0.0, |points| {
+                asked += points.len();
+                // A rugged surface on which contractions often fail, forcing shrinks
+                Ok(points
+                    .iter()
+                    .map(|p| (p.iter().sum::<f64>() * 1000.0).sin())
+                    .collect())
+            })
+            .unwrap();
+            assert!(asked <= max_evaluations, "{} > {}", asked, max_evaluations);
+        }
+    }
+
+    #[test]
+    fn abc_accepts_the_closest_fraction_of_the_sample() {
+        let errors = [0.5, f64::INFINITY, 0.1, 0.9, 0.3, 0.2, 0.7, 0.4];
+        let evaluations = evaluations(&errors);
+        assert_eq!(
+            spec(CalibrationMethod::Abc, 0.25).accepted(&evaluations),
+            vec![2, 5]
+        );
+        // The accepted count is rounded up and never zero
+        assert_eq!(
+            spec(CalibrationMethod::Abc, 0.3).accepted(&evaluations),
+            vec![2, 5, 4]
+        );
+        assert_eq!(
+            spec(CalibrationMethod::Abc, 0.01).accepted(&evaluations),
+            vec![2]
+        );
+        assert_eq!(
+            spec(CalibrationMethod::Abc, 1.0)
+                .accepted(&evaluations)
+                .len(),
+            8
+        );
+        assert_eq!(
+            spec(CalibrationMethod::NelderMead, 0.5).accepted(&evaluations),
+            vec![2]
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::config_error::ConfigError;
+use crate::environment::Environment;
+use crate::environment_config::EnvironmentConfig;
+
+/// Leading bytes of every checkpoint file; the last byte is the format version.
+const MAGIC: &[u8; 8] = b"WDSCKPT1";
+
+/// Writes `env`, including its RNG state, to a compact binary file. The file is
+/// replaced atomically, so an interrupted save keeps the previous checkpoint.
+pub(crate) fn save(env: &Environment, path: &Path) -> Result<(), ConfigError> {
+    let io_error = |source| ConfigError::Io {
+        path: path.display().to_string(),
+        source,
+    };
+    let temporary = path.with_extension("checkpoint.tmp");
+    let mut file = BufWriter::new(File::create(&temporary).map_err(io_error)?);
+    file.write_all(MAGIC).map_err(io_error)?;
+    bincode::serialize_into(&mut file, env).map_err(|e| ConfigError::Parse {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    })?;
+    file.flush().map_err(io_error)?;
+    drop(file);
+    fs::rename(&temporary, path).map_err(io_error)
+}
+
+/// Reads an environment written by [`save`]. Metrics sinks are not part of a
+/// checkpoint and have to be attached again.
+pub(crate) fn load(path: &Path) -> Result<Environment, ConfigError> {
+    let parse_error = |message: String| ConfigError::Parse {
+        path: path.display().to_string(),
+        message,
+    };
+    let mut file = BufReader::new(File::open(path).map_err(|source| ConfigError::Io {
+        path: path.display().to_string(),
+        source,
+    })?);
+    let mut magic = [0; 8];
+    if file.read_exact(&mut magic).is_err() || &magic != MAGIC {
+        return Err(parse_error("not a checkpoint file".to_string()));
+    }
+    bincode::deserialize_from(file).map_err(|e| parse_error(e.to_string()))
+}
+
+/// Stores the config as JSON inside the binary checkpoint, since its schedule
+/// values are untyped JSON that a binary format cannot describe.
+pub(crate) mod config_as_json {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        config: &EnvironmentConfig,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serde_json::to_string(config)
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<EnvironmentConfig, D::Error> {
+        let json = String::deserialize(deserializer)?;
+        serde_json::from_str(&json).map_err(serde::de::Error::custom)
+    }
+}
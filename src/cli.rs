@@ -0,0 +1,202 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Agent-based simulation of wealth and education distribution"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the simulation headless and write metrics to a CSV file
+    Run(RunArgs),
+    /// Run the simulation with a visualisation window
+    Gui(RunArgs),
+    /// Continue a run from a checkpoint
+    Resume(ResumeArgs),
+    /// Fork a checkpoint into branches with different config fields and compare them
+    Branch(BranchArgs),
+    /// Write the default configuration to a file
+    InitConfig {
+        /// Where to write the configuration
+        #[arg(default_value = "config/default.json")]
+        path: PathBuf,
+        /// Overwrite the file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check that a configuration file can be loaded, without running it
+    Validate {
+        /// Configuration files to check
+        #[arg(required = true)]
+        configs: Vec<PathBuf>,
+    },
+    /// Upgrade configuration files to the current schema version, in place
+    Migrate {
+        /// Configuration files to rewrite
+        #[arg(required = true)]
+        configs: Vec<PathBuf>,
+    },
+    /// Print the JSON Schema of the configuration format
+    Schema {
+        /// Write the schema to this file instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Run configurations headless in parallel, optionally over a grid of field values
+    Sweep(SweepArgs),
+    /// Global sensitivity analysis (LHS, Morris or Sobol) of config parameters
+    Sensitivity {
+        /// Analysis spec with the method, parameter ranges and outputs
+        spec: PathBuf,
+        /// Base configuration the sampled parameters are applied to
+        #[arg(short, long, default_value = "config/default.json")]
+        config: PathBuf,
+        /// Directory for the samples and indices CSV files
+        #[arg(short, long, default_value = "visualisation")]
+        output_dir: PathBuf,
+        /// Number of runs executed at once [default: number of CPU cores]
+        #[arg(short, long)]
+        jobs: Option<usize>,
+        #[command(flatten)]
+        overrides: RunOverrides,
+    },
+    /// Fit config parameters to target metrics with Nelder–Mead or ABC
+    Calibrate {
+        /// Calibration spec with the method, targets and parameter ranges
+        spec: PathBuf,
+        /// Base configuration the searched parameters are applied to
+        #[arg(short, long, default_value = "config/default.json")]
+        config: PathBuf,
+        /// Directory for the evaluations CSV and the best-fit config
+        #[arg(short, long, default_value = "visualisation")]
+        output_dir: PathBuf,
+        /// Number of runs executed at once [default: number of CPU cores]
+        #[arg(short, long)]
+        jobs: Option<usize>,
+        #[command(flatten)]
+        overrides: RunOverrides,
+    },
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// Configuration file
+    #[arg(default_value = "config/default.json")]
+    pub config: PathBuf,
+    /// Metrics CSV path [default: visualisation/metrics_<config name>.csv]
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Do not write metrics
+    #[arg(long)]
+    pub no_metrics: bool,
+    /// Save a checkpoint every N iterations and at the end of the run
+    #[arg(long, value_name = "N")]
+    pub checkpoint_every: Option<usize>,
+    /// Checkpoint file [default: the metrics path with a .checkpoint extension]
+    #[arg(long, requires = "checkpoint_every")]
+    pub checkpoint: Option<PathBuf>,
+    /// Write the final population as a CSV that `initial_population` accepts
+    #[arg(long, value_name = "PATH")]
+    pub save_agents: Option<PathBuf>,
+    /// Number of threads that update agents [default: number of CPU cores]
+    #[arg(short, long)]
+    pub jobs: Option<usize>,
+    /// Compute and write metrics on a separate thread
+    #[arg(long)]
+    pub background_metrics: bool,
+    #[command(flatten)]
+    pub overrides: RunOverrides,
+}
+
+#[derive(Args)]
+pub struct ResumeArgs {
+    /// Checkpoint file written by `run --checkpoint-every`
+    pub checkpoint: PathBuf,
+    /// Metrics CSV to continue [default: the checkpoint path with a .csv extension]
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Do not write metrics
+    #[arg(long)]
+    pub no_metrics: bool,
+    /// Run until this iteration instead of the `num_iterations` of the checkpoint
+    #[arg(short = 'n', long)]
+    pub iterations: Option<usize>,
+    /// Write metrics every N iterations
+    #[arg(short = 'i', long, default_value_t = 1)]
+    pub metrics_interval: usize,
+    /// Compute and write metrics on a separate thread
+    #[arg(long)]
+    pub background_metrics: bool,
+    /// Keep updating the checkpoint every N iterations and at the end of the run
+    #[arg(long, value_name = "N")]
+    pub checkpoint_every: Option<usize>,
+    /// Continue with the visualisation window
+    #[arg(long)]
+    pub gui: bool,
+}
+
+#[derive(Args)]
+pub struct BranchArgs {
+    /// Checkpoint file all branches start from
+    pub checkpoint: PathBuf,
+    /// Branch name and its overrides, e.g. `--branch high_tax:transaction.tax_rate=0.2`.
+    /// A `baseline` branch without overrides is always run
+    #[arg(long = "branch", value_name = "NAME:PATH=VALUE;...", required = true)]
+    pub branches: Vec<String>,
+    /// Run every branch until this iteration instead of the checkpoint's `num_iterations`
+    #[arg(short = 'n', long)]
+    pub iterations: Option<usize>,
+    /// Directory for the branch metrics files and the difference CSV
+    #[arg(short, long, default_value = "visualisation")]
+    pub output_dir: PathBuf,
+    /// Number of branches run at once [default: number of CPU cores]
+    #[arg(short, long)]
+    pub jobs: Option<usize>,
+    /// Write metrics every N iterations
+    #[arg(short = 'i', long, default_value_t = 1)]
+    pub metrics_interval: usize,
+}
+
+#[derive(Args)]
+pub struct SweepArgs {
+    /// Base configuration files
+    #[arg(required = true)]
+    pub configs: Vec<PathBuf>,
+    /// Field to vary and its values, e.g. `--vary transaction.tax_rate=0,0.05,0.1`
+    #[arg(long, value_name = "PATH=V1,V2,...")]
+    pub vary: Vec<String>,
+    /// Pair the i-th values of all varied fields instead of running every combination
+    #[arg(long)]
+    pub zip: bool,
+    /// Directory for the metrics files and the run index
+    #[arg(short, long, default_value = "visualisation")]
+    pub output_dir: PathBuf,
+    /// Number of runs executed at once [default: number of CPU cores]
+    #[arg(short, long)]
+    pub jobs: Option<usize>,
+    #[command(flatten)]
+    pub overrides: RunOverrides,
+}
+
+#[derive(Args)]
+pub struct RunOverrides {
+    /// Number of iterations, overrides `num_iterations` from the config
+    #[arg(short = 'n', long)]
+    pub iterations: Option<usize>,
+    /// Random seed, overrides `seed` from the config
+    #[arg(short, long)]
+    pub seed: Option<u64>,
+    /// Override a config field by its dotted path, e.g. `--set transaction.tax_rate=0.1`
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    pub set: Vec<String>,
+    /// Write metrics every N iterations
+    #[arg(short = 'i', long, default_value_t = 1)]
+    pub metrics_interval: usize,
+}
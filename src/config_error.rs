@@ -0,0 +1,55 @@
+use std::fmt;
+use std::io;
+
+/// A single field of the configuration that breaks a constraint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    pub path: String, // dotted JSON path, e.g. `transaction.tax_rate`
+    pub constraint: String,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io { path: String, source: io::Error },
+    Parse { path: String, message: String },
+    Override { path: String, message: String },
+    ExtendsCycle(Vec<String>), // files in the `extends` chain, ending with the repeated one
+    Invalid(Vec<ValidationIssue>),
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.constraint)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => write!(f, "{}: {}", path, source),
+            ConfigError::Parse { path, message } => write!(f, "{}: {}", path, message),
+            ConfigError::Override { path, message } => {
+                write!(f, "cannot set {}: {}", path, message)
+            }
+            ConfigError::ExtendsCycle(chain) => {
+                write!(f, "cyclic `extends` chain: {}", chain.join(" -> "))
+            }
+            ConfigError::Invalid(issues) => {
+                write!(f, "invalid configuration ({} issues)", issues.len())?;
+                for issue in issues {
+                    write!(f, "\n  {}", issue)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
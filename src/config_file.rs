@@ -0,0 +1,312 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config_error::ConfigError;
+use crate::config_migration;
+
+const EXTENDS_KEY: &str = "extends";
+const INITIAL_POPULATION_KEY: &str = "initial_population";
+
+/// On-disk config format, chosen by file extension. Anything that is not
+/// `.toml`, `.yaml` or `.yml` is read and written as JSON.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("toml") => ConfigFormat::Toml,
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    fn parse(self, content: &str) -> Result<Value, String> {
+        match self {
+            ConfigFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => toml::from_str(content).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn render<T: Serialize>(self, value: &T) -> Result<String, String> {
+        match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => toml::to_string_pretty(value).map_err(|e| e.to_string()),
+            // Through JSON, so that enums become maps rather than YAML tags, which
+            // the JSON values files are read into cannot hold
+            ConfigFormat::Yaml => serde_json::to_value(value)
+                .map_err(|e| e.to_string())
+                .and_then(|value| serde_yaml::to_string(&value).map_err(|e| e.to_string())),
+        }
+    }
+
+    /// Character between a key and its value.
+    fn separator(self) -> char {
+        match self {
+            ConfigFormat::Json | ConfigFormat::Yaml => ':',
+            ConfigFormat::Toml => '=',
+        }
+    }
+
+    /// Renames every occurrence of the key `old` in `content` to `new`, leaving
+    /// the rest of the text, including comments, as it is.
+    fn rename_key(self, content: &str, old: &str, new: &str) -> String {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+        let mut renamed = String::with_capacity(content.len());
+        let mut rest = content;
+        while let Some(start) = rest.find(old) {
+            let (before, after) = (&rest[..start], &rest[start + old.len()..]);
+            let is_key = !before.ends_with(is_word)
+                && !after.starts_with(is_word)
+                && after
+                    .trim_start_matches(['"', '\''])
+                    .trim_start_matches([' ', '\t'])
+                    .starts_with(self.separator());
+            renamed.push_str(before);
+            renamed.push_str(if is_key { new } else { old });
+            rest = after;
+        }
+        renamed.push_str(rest);
+        renamed
+    }
+
+    /// Sets the top-level `key` to `value` on its own line, keeping any comment
+    /// after it, or inserts it as the first key of the document.
+    fn set_top_level(self, content: &str, key: &str, value: u32) -> String {
+        let line = format!(
+            "{}{} {}",
+            key,
+            if self == ConfigFormat::Toml {
+                " ="
+            } else {
+                ":"
+            },
+            value
+        );
+        let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+        // Whatever follows the old value on its line, such as a comment
+        let existing = lines.iter().enumerate().find_map(|(i, l)| {
+            let value = l
+                .strip_prefix(key)?
+                .trim_start()
+                .strip_prefix(self.separator())?
+                .trim_start();
+            let end = value.find(char::is_whitespace).unwrap_or(value.len());
+            Some((i, value[end..].to_string()))
+        });
+        match existing {
+            Some((i, trailing)) => lines[i] = line + &trailing,
+            None => {
+                let start = usize::from(lines.first().is_some_and(|l| l.trim_end() == "---"));
+                lines.insert(start, line);
+            }
+        }
+        let mut edited = lines.join("\n");
+        if content.ends_with('\n') {
+            edited.push('\n');
+        }
+        edited
+    }
+}
+
+/// Reads a config file into a JSON value, resolving its `extends` chain.
+///
+/// A file may name a base file (relative to its own directory) under `extends`.
+/// The base is loaded first and the file's own fields are merged over it,
+/// nested sections field by field. A relative `initial_population` is resolved
+/// against the directory of the file that sets it, into an absolute path so that
+/// it still holds when the config is saved elsewhere.
+///
+/// Also returns a warning, prefixed with its file, for every deprecated field
+/// that was upgraded on the way.
+pub(crate) fn read_layered(path: &Path) -> Result<(Value, Vec<String>), ConfigError> {
+    let mut warnings = Vec::new();
+    let value = read_with_chain(path, &mut Vec::new(), &mut warnings)?;
+    Ok((value, warnings))
+}
+
+fn read_with_chain(
+    path: &Path,
+    chain: &mut Vec<PathBuf>,
+    warnings: &mut Vec<String>,
+) -> Result<Value, ConfigError> {
+    let canonical = fs::canonicalize(path).map_err(|source| ConfigError::Io {
+        path: path.display().to_string(),
+        source,
+    })?;
+    if chain.contains(&canonical) {
+        let mut cycle: Vec<String> = chain.iter().map(|p| p.display().to_string()).collect();
+        cycle.push(canonical.display().to_string());
+        return Err(ConfigError::ExtendsCycle(cycle));
+    }
+    let directory = canonical.parent().unwrap_or(Path::new("")).to_path_buf();
+    chain.push(canonical);
+
+    let (mut value, file_warnings) = read_migrated(path)?;
+    warnings.extend(
+        file_warnings
+            .into_iter()
+            .map(|warning| format!("{}: {}", path.display(), warning)),
+    );
+    if let Some(Value::String(population)) = value.get_mut(INITIAL_POPULATION_KEY) {
+        *population = directory.join(&*population).to_string_lossy().into_owned();
+    }
+    let base = match value.as_object_mut().and_then(|o| o.remove(EXTENDS_KEY)) {
+        None => None,
+        Some(Value::String(base)) => Some(base),
+        Some(_) => {
+            return Err(ConfigError::Parse {
+                path: path.display().to_string(),
+                message: format!("`{}` must be a file path", EXTENDS_KEY),
+            })
+        }
+    };
+
+    if let Some(base) = base {
+        let base_path = path.parent().unwrap_or(Path::new("")).join(base);
+        let mut merged = read_with_chain(&base_path, chain, warnings)?;
+        merge(&mut merged, value);
+        value = merged;
+    }
+
+    chain.pop();
+    Ok(value)
+}
+
+fn read_value(path: &Path) -> Result<Value, ConfigError> {
+    let content = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.display().to_string(),
+        source,
+    })?;
+    ConfigFormat::from_path(path)
+        .parse(&content)
+        .map_err(|message| ConfigError::Parse {
+            path: path.display().to_string(),
+            message,
+        })
+}
+
+/// Reads a single file (ignoring `extends`) and upgrades it to the current schema,
+/// returning a warning for every deprecated field.
+fn read_migrated(path: &Path) -> Result<(Value, Vec<String>), ConfigError> {
+    let mut value = read_value(path)?;
+    let warnings = config_migration::migrate(&mut value).map_err(|message| ConfigError::Parse {
+        path: path.display().to_string(),
+        message,
+    })?;
+    Ok((value, warnings))
+}
+
+/// Rewrites a config file in place in the current schema. Its `extends`
+/// reference and the set of fields it defines are kept as they are. TOML and
+/// YAML files are edited as text so that their comments survive; if that
+/// cannot reproduce the migrated config, the file is left alone and the error
+/// lists the edits to make by hand. Returns `None` for files that are already
+/// in the current schema, which are not touched either.
+pub(crate) fn migrate_in_place(path: &Path) -> Result<Option<Vec<String>>, ConfigError> {
+    let parse_error = |message: String| ConfigError::Parse {
+        path: path.display().to_string(),
+        message,
+    };
+    let version = config_migration::schema_version(&read_value(path)?).map_err(parse_error)?;
+    if version == config_migration::CURRENT_SCHEMA_VERSION {
+        return Ok(None);
+    }
+    let (value, warnings) = read_migrated(path)?;
+    let format = ConfigFormat::from_path(path);
+    if format == ConfigFormat::Json {
+        write(path, &value)?;
+        return Ok(Some(warnings));
+    }
+
+    let content = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.display().to_string(),
+        source,
+    })?;
+    let mut edited = content.clone();
+    let mut edits = Vec::new();
+    for (old, new) in config_migration::renamed_fields(version) {
+        let (old, new) = (leaf(old), leaf(new));
+        edited = format.rename_key(&edited, old, new);
+        edits.push(format!("rename `{}` to `{}`", old, new));
+    }
+    edited = format.set_top_level(
+        &edited,
+        config_migration::VERSION_KEY,
+        config_migration::CURRENT_SCHEMA_VERSION,
+    );
+    edits.push(format!(
+        "set `{}` to {}",
+        config_migration::VERSION_KEY,
+        config_migration::CURRENT_SCHEMA_VERSION
+    ));
+
+    if format.parse(&edited).ok().as_ref() != Some(&value) {
+        return Err(parse_error(format!(
+            "cannot be migrated as text and rewriting it would drop its comments, edit it by hand: {}",
+            edits.join(", ")
+        )));
+    }
+    fs::write(path, edited).map_err(|source| ConfigError::Io {
+        path: path.display().to_string(),
+        source,
+    })?;
+    Ok(Some(warnings))
+}
+
+/// Last key of a dotted path.
+fn leaf(path: &str) -> &str {
+    path.rsplit('.').next().unwrap_or(path)
+}
+
+/// Reads any JSON, TOML or YAML document, such as an analysis spec, into `T`.
+pub(crate) fn read_document<T: DeserializeOwned>(path: &Path) -> Result<T, ConfigError> {
+    serde_json::from_value(read_value(path)?).map_err(|e| ConfigError::Parse {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    })
+}
+
+/// Writes `value` in the format matching the extension of `path`.
+pub(crate) fn write<T: Serialize>(path: &Path, value: &T) -> Result<(), ConfigError> {
+    let content = ConfigFormat::from_path(path)
+        .render(value)
+        .map_err(|message| ConfigError::Parse {
+            path: path.display().to_string(),
+            message,
+        })?;
+    fs::write(path, content).map_err(|source| ConfigError::Io {
+        path: path.display().to_string(),
+        source,
+    })
+}
+
+/// Merges `overlay` into `base`: objects key by key, anything else replaced.
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
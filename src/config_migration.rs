@@ -0,0 +1,115 @@
+use serde_json::{Map, Value};
+
+/// Schema version written by this build. Files without `schema_version` are version 1.
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+pub(crate) const VERSION_KEY: &str = "schema_version";
+
+/// A field that was renamed in a given schema version. Old names are still
+/// accepted and rewritten, with a warning.
+struct RenamedField {
+    since: u32,
+    old: &'static str,
+    new: &'static str,
+}
+
+const RENAMED_FIELDS: &[RenamedField] = &[RenamedField {
+    since: 2,
+    old: "income_and_consumption.aditional_consumption_rate",
+    new: "income_and_consumption.additional_consumption_rate",
+}];
+
+/// Upgrades one config file's raw value to [`CURRENT_SCHEMA_VERSION`] in place
+/// and returns warnings about the deprecated fields it found.
+///
+/// Only fields present in the value are touched, so partial files used with
+/// `extends` keep describing just the fields they override.
+pub(crate) fn migrate(value: &mut Value) -> Result<Vec<String>, String> {
+    let version = schema_version(value)?;
+    let object = value
+        .as_object_mut()
+        .ok_or_else(|| "config must be an object".to_string())?;
+
+    let mut warnings = Vec::new();
+    for field in RENAMED_FIELDS {
+        if version < field.since && rename(object, field.old, field.new) {
+            warnings.push(format!(
+                "`{}` is deprecated since schema version {}, use `{}`",
+                field.old, field.since, field.new
+            ));
+        }
+    }
+
+    // Keep the version as the first key so it is visible at the top of the file
+    let mut upgraded = Map::new();
+    upgraded.insert(VERSION_KEY.to_string(), Value::from(CURRENT_SCHEMA_VERSION));
+    upgraded.extend(
+        std::mem::take(object)
+            .into_iter()
+            .filter(|(k, _)| k != VERSION_KEY),
+    );
+    *object = upgraded;
+    Ok(warnings)
+}
+
+/// Schema version of one config file's raw value, 1 if it has none.
+pub(crate) fn schema_version(value: &Value) -> Result<u32, String> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| "config must be an object".to_string())?;
+    let version = match object.get(VERSION_KEY) {
+        None => 1,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| format!("`{}` must be a positive integer", VERSION_KEY))?,
+    };
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(format!(
+            "schema_version {} is newer than the supported version {}",
+            version, CURRENT_SCHEMA_VERSION
+        ));
+    }
+    Ok(version)
+}
+
+/// Deprecated fields that [`migrate`] renames in a file of schema `version`,
+/// as `(old, new)` dotted paths.
+pub(crate) fn renamed_fields(version: u32) -> impl Iterator<Item = (&'static str, &'static str)> {
+    RENAMED_FIELDS
+        .iter()
+        .filter(move |field| version < field.since)
+        .map(|field| (field.old, field.new))
+}
+
+/// Moves the value at dotted path `old` to `new`. Both paths must share a parent.
+fn rename(object: &mut Map<String, Value>, old: &str, new: &str) -> bool {
+    let (parent, old_key) = old.rsplit_once('.').unwrap_or(("", old));
+    let new_key = new.rsplit('.').next().unwrap_or(new);
+
+    let mut section = object;
+    if !parent.is_empty() {
+        for key in parent.split('.') {
+            match section.get_mut(key).and_then(Value::as_object_mut) {
+                Some(inner) => section = inner,
+                None => return false,
+            }
+        }
+    }
+
+    if !section.contains_key(old_key) {
+        return false;
+    }
+    // Rebuild the section so the renamed key stays in its original position
+    *section = std::mem::take(section)
+        .into_iter()
+        .map(|(k, v)| {
+            if k == old_key {
+                (new_key.to_string(), v)
+            } else {
+                (k, v)
+            }
+        })
+        .collect();
+    true
+}
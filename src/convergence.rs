@@ -0,0 +1,318 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+
+use crate::config_error::{ConfigError, ValidationIssue};
+use crate::config_file;
+use crate::metrics::{mean_and_std, Stats};
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ConvergenceCriterion {
+    /// Absolute least-squares slope per iteration over the window.
+    Slope,
+    /// Sample variance over the window.
+    Variance,
+}
+
+/// Optional early stopping once the selected metrics have flattened.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct Convergence {
+    /// Metrics columns that must all be steady, e.g. `gini` or `p50`.
+    #[schemars(length(min = 1))]
+    pub metrics: Vec<String>,
+    pub criterion: ConvergenceCriterion,
+    /// Number of most recent iterations the criterion is computed over.
+    #[schemars(range(min = 2))]
+    pub window: usize,
+    /// The run stops once the criterion of every metric is below this value.
+    #[schemars(range(min = 0.0))]
+    pub threshold: f64,
+    /// Divide the slope by the window mean and the variance by its square, so
+    /// one threshold fits metrics of different scales.
+    #[serde(default)]
+    pub relative: bool,
+    /// Iterations that always run before convergence is checked.
+    #[serde(default)]
+    pub min_iterations: usize,
+}
+
+/// Tracks the recent statistics of a run and detects when they become steady.
+#[derive(Serialize, Deserialize)]
+pub struct ConvergenceMonitor {
+    criteria: Convergence,
+    recent: VecDeque<Stats>,
+    converged_at: Option<usize>,
+}
+
+/// Outcome of a run with convergence criteria, written next to its metrics.
+#[derive(Serialize, Clone, Debug)]
+pub struct ConvergenceSummary {
+    pub converged: bool,
+    /// Iteration at which every selected metric was first steady.
+    pub convergence_iteration: Option<usize>,
+    pub iterations_run: usize,
+    pub window: usize,
+    /// Mean of every metrics column over the last `window` iterations.
+    pub steady_state: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Convergence {
+    /// Reports issues under `prefix`, e.g. `convergence.window`.
+    pub(crate) fn validate(&self, prefix: &str, issues: &mut Vec<ValidationIssue>) {
+        let mut check = |ok: bool, field: &str, constraint: &str| {
+            if !ok {
+                issues.push(ValidationIssue {
+                    path: format!("{}.{}", prefix, field),
+                    constraint: constraint.to_string(),
+                });
+            }
+        };
+        check(
+            !self.metrics.is_empty(),
+            "metrics",
+            "must name at least one column",
+        );
+        for metric in &self.metrics {
+            check(
+                Stats::COLUMN_NAMES.contains(&metric.as_str()),
+                "metrics",
+                &format!(
+                    "`{}` must be one of {}",
+                    metric,
+                    Stats::COLUMN_NAMES.join(", ")
+                ),
+            );
+        }
+        check(self.window >= 2, "window", "must be at least 2");
+        check(
+            self.threshold.is_finite() && self.threshold >= 0.0,
+            "threshold",
+            "must be at least 0",
+        );
+    }
+}
+
+impl ConvergenceMonitor {
+    pub fn new(criteria: Convergence) -> Self {
+        Self {
+            recent: VecDeque::with_capacity(criteria.window),
+            criteria,
+            converged_at: None,
+        }
+    }
+
+    /// Adds the statistics of the latest iteration and returns whether the run
+    /// has converged.
+    pub fn observe(&mut self, stats: &Stats) -> bool {
+        if self.converged_at.is_some() {
+            return true;
+        }
+        if self.recent.len() == self.criteria.window {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(*stats);
+
+        if self.recent.len() < self.criteria.window
+            || stats.iteration < self.criteria.min_iterations
+        {
+            return false;
+        }
+        let steady = self.criteria.metrics.iter().all(|metric| {
+            let values: Vec<f64> = self
+                .recent
+                .iter()
+                .filter_map(|s| s.column(metric))
+                .collect();
+            self.criterion(&values) < self.criteria.threshold
+        });
+        if steady {
+            self.converged_at = Some(stats.iteration);
+        }
+        steady
+    }
+
+    fn criterion(&self, values: &[f64]) -> f64 {
+        let (mean, std) = mean_and_std(values);
+        let scale = if self.criteria.relative && mean != 0.0 {
+            mean.abs()
+        } else {
+            1.0
+        };
+        match self.criteria.criterion {
+            ConvergenceCriterion::Slope => {
+                let n = values.len() as f64;
+                let x_mean = (n - 1.0) / 2.0;
+                let (mut covariance, mut x_variance) = (0.0, 0.0);
+                for (x, y) in values.iter().enumerate() {
+                    covariance += (x as f64 - x_mean) * (y - mean);
+                    x_variance += (x as f64 - x_mean).powi(2);
+                }
+                (covariance / x_variance).abs() / scale
+            }
+            ConvergenceCriterion::Variance => std.powi(2) / scale.powi(2),
+        }
+    }
+
+    pub fn converged_at(&self) -> Option<usize> {
+        self.converged_at
+    }
+
+    /// Summary of the run so far, `iterations_run` being the current iteration.
+    pub fn summary(&self, iterations_run: usize) -> ConvergenceSummary {
+        let steady_state = Stats::COLUMN_NAMES
+            .iter()
+            .map(|&column| {
+                let values: Vec<f64> = self
+                    .recent
+                    .iter()
+                    .filter_map(|s| s.column(column))
+                    .collect();
+                let mean = values.iter().sum::<f64>() / values.len() as f64;
+                (column.to_string(), serde_json::Value::from(mean))
+            })
+            .collect();
+        ConvergenceSummary {
+            converged: self.converged_at.is_some(),
+            convergence_iteration: self.converged_at,
+            iterations_run,
+            window: self.recent.len(),
+            steady_state,
+        }
+    }
+}
+
+impl ConvergenceSummary {
+    /// Saves the summary as JSON, TOML or YAML depending on the file extension.
+    pub fn save_to_file(&self, path: &str) -> Result<(), ConfigError> {
+        config_file::write(Path::new(path), self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stats at `iteration` with the given Gini and median and every other column 0.
+    fn stats(iteration: usize, gini: f64, p50: f64) -> Stats {
+        let mut fields = serde_json::Map::new();
+        fields.insert("iteration".to_string(), iteration.into());
+        for name in Stats::COLUMN_NAMES {
+            fields.insert(name.to_string(), 0.into());
+        }
+        fields.insert("gini".to_string(), gini.into());
+        fields.insert("p50".to_string(), p50.into());
+        serde_json::from_value(fields.into()).unwrap()
+    }
+
+    fn criteria(criterion: ConvergenceCriterion, window: usize, threshold: f64) -> Convergence {
+        Convergence {
+            metrics: vec!["gini".to_string()],
+            criterion,
+            window,
+            threshold,
+            relative: false,
+            min_iterations: 0,
+        }
+    }
+
+    /// Observes iterations 1 to `iterations` with the Gini and median of `series`
+    /// and returns the monitor.
+    fn observe(
+        criteria: Convergence,
+        iterations: usize,
+        series: impl Fn(usize) -> (f64, f64),
+    ) -> ConvergenceMonitor {
+        let mut monitor = ConvergenceMonitor::new(criteria);
+        for iteration in 1..=iterations {
+            let (gini, p50) = series(iteration);
+            monitor.observe(&stats(iteration, gini, p50));
+        }
+        monitor
+    }
+
+    #[test]
+    fn slope_converges_once_the_window_is_flat() {
+        use ConvergenceCriterion::*;
+        // Rises until iteration 10 and stays flat after it
+        let rising_then_flat = |i: usize| (i.min(10) as f64 * 0.01, 1.0);
+        let monitor = observe(criteria(Slope, 5, 1e-6), 30, rising_then_flat);
+        assert_eq!(monitor.converged_at(), Some(14));
+
+        let rising = |i: usize| (i as f64 * 0.01, 1.0);
+        let monitor = observe(criteria(Slope, 5, 1e-3), 30, rising);
+        assert_eq!(monitor.converged_at(), None);
+    }
+
+    #[test]
+    fn variance_catches_oscillations_without_a_trend() {
+        use ConvergenceCriterion::*;
+        let oscillating = |i: usize| (if i % 2 == 1 { 0.6 } else { 0.4 }, 1.0);
+        // Over an odd window starting on a peak the slope is 0
+        let monitor = observe(criteria(Slope, 5, 1e-6), 5, oscillating);
+        assert_eq!(monitor.converged_at(), Some(5));
+        let monitor = observe(criteria(Variance, 5, 1e-3), 30, oscillating);
+        assert_eq!(monitor.converged_at(), None);
+
+        let settling = |i: usize| (0.5 + 0.1 * 0.5f64.powi(i as i32), 1.0);
+        let monitor = observe(criteria(Variance, 4, 1e-6), 30, settling);
+        let converged_at = monitor.converged_at().unwrap();
+        assert!((5..30).contains(&converged_at), "{}", converged_at);
+    }
+
+    #[test]
+    fn convergence_waits_for_min_iterations_and_every_metric() {
+        use ConvergenceCriterion::*;
+        let flat = |_: usize| (0.5, 1.0);
+        assert_eq!(
+            observe(criteria(Variance, 3, 1e-9), 20, flat).converged_at(),
+            Some(3)
+        );
+        let waiting = Convergence {
+            min_iterations: 12,
+            ..criteria(Variance, 3, 1e-9)
+        };
+        assert_eq!(observe(waiting, 20, flat).converged_at(), Some(12));
+
+        // The median keeps rising, so the run never converges on both
+        let both = Convergence {
+            metrics: vec!["gini".to_string(), "p50".to_string()],
+            ..criteria(Slope, 3, 1e-3)
+        };
+        let rising_median = |i: usize| (0.5, 1000.0 + i as f64);
+        assert_eq!(
+            observe(both.clone(), 20, rising_median).converged_at(),
+            None
+        );
+        // Relative to its mean of about 1000 the median's slope of 1 is small
+        let relative = Convergence {
+            relative: true,
+            ..both
+        };
+        assert_eq!(observe(relative, 20, rising_median).converged_at(), Some(3));
+    }
+
+    #[test]
+    fn summary_averages_the_window_the_run_converged_on() {
+        use ConvergenceCriterion::*;
+        let rising_then_flat = |i: usize| (i.min(10) as f64 * 0.01, i as f64);
+        let monitor = observe(criteria(Slope, 4, 1e-6), 30, rising_then_flat);
+        let summary = monitor.summary(13);
+        assert!(summary.converged);
+        assert_eq!(summary.convergence_iteration, Some(13));
+        assert_eq!(summary.iterations_run, 13);
+        assert_eq!(summary.window, 4);
+        assert_eq!(summary.steady_state.len(), Stats::COLUMN_COUNT);
+        assert!((summary.steady_state["gini"].as_f64().unwrap() - 0.1).abs() < 1e-12);
+        // Iterations 10 to 13
+        assert_eq!(summary.steady_state["p50"].as_f64().unwrap(), 11.5);
+
+        let rising = |i: usize| (i as f64 * 0.01, 1.0);
+        let summary = observe(criteria(Slope, 4, 1e-6), 2, rising).summary(2);
+        assert!(!summary.converged);
+        assert_eq!(summary.convergence_iteration, None);
+        assert_eq!(summary.window, 2);
+        assert!((summary.steady_state["gini"].as_f64().unwrap() - 0.015).abs() < 1e-12);
+    }
+}
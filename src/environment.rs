@@ -1,29 +1,94 @@
-use crate::agent::Agent;
-use crate::environment_config::{EnvironmentConfig, Wealth};
-use crate::metrics::Metrics;
+use crate::agent::{Agent, AgentId};
+use crate::agent_store::AgentStore;
+use crate::checkpoint;
+use crate::config_error::ConfigError;
+use crate::convergence::{ConvergenceMonitor, ConvergenceSummary};
+use crate::environment_config::EnvironmentConfig;
+use crate::government::Budget;
+use crate::index_set::IndexSet;
+use crate::metrics::{Metrics, MetricsSink, Snapshot, Stats};
+use crate::population;
+use crate::spatial::SpatialGrid;
 use rand::seq::SliceRandom;
-use rand::Rng;
-use std::collections::HashMap;
-
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Simulation state. Everything but the attached sinks and checkpoint settings
+/// is saved in checkpoints.
+#[derive(Serialize, Deserialize)]
 pub struct Environment {
-    pub agents: Vec<Agent>,
+    pub agents: AgentStore,
     pub iteration: usize,
     pub min_x: usize,
     pub max_x: usize,
     pub min_y: usize,
     pub max_y: usize,
-    pub next_agent_id: usize,
+    #[serde(with = "checkpoint::config_as_json")]
     pub config: EnvironmentConfig,
     pub iteration_total_transaction_amount: f64,
     pub iteration_total_transaction_count: u32,
+    pub budget: Budget,
+    pub rng: ChaCha8Rng,
+    #[serde(skip)]
+    sinks: Vec<Box<dyn MetricsSink + Send>>,
+    convergence: Option<ConvergenceMonitor>,
+    #[serde(skip)]
+    checkpoints: Option<(PathBuf, usize)>, // file and interval in iterations
 }
 
 impl Environment {
+    /// Panics if the config's `initial_population` file cannot be loaded; use
+    /// [`Environment::try_new`] to handle that error.
     pub fn new(config: &EnvironmentConfig) -> Self {
+        Self::try_new(config).unwrap_or_else(|e| panic!("Failed to create environment: {}", e))
+    }
+
+    /// Creates the initial population, reading it from `initial_population` if set.
+    pub fn try_new(config: &EnvironmentConfig) -> Result<Self, ConfigError> {
+        // Unseeded runs draw a seed so that `config()` always records the one used
+        let mut config = config.clone();
+        let seed = *config
+            .seed
+            .get_or_insert_with(|| rand::random::<u32>() as u64);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let agents = match &config.initial_population {
+            Some(path) => {
+                let records = population::read(Path::new(path), &config)?;
+                config.num_agents = records.len();
+                population::agents(&records, &config, &mut rng)
+            }
+            None => Self::random_population(&config, &mut rng),
+        };
+
+        Ok(Self {
+            agents,
+            iteration: 0,
+            min_x: 0,
+            max_x: config.length,
+            min_y: 0,
+            max_y: config.width,
+            iteration_total_transaction_amount: 0.0,
+            iteration_total_transaction_count: 0,
+            budget: Budget::default(),
+            rng,
+            sinks: Vec::new(),
+            checkpoints: None,
+            convergence: config.convergence.clone().map(ConvergenceMonitor::new),
+            config,
+        })
+    }
+
+    /// Agents with random age, education, wealth and position, and parent links
+    /// inferred from their ages.
+    fn random_population(config: &EnvironmentConfig, rng: &mut ChaCha8Rng) -> AgentStore {
         let mut agents: Vec<Agent> = (0..config.num_agents)
             .map(|id| {
                 Agent::new(
-                    id,
+                    AgentId(id),
                     0.0,
                     config.length as f64,
                     0.0,
@@ -31,11 +96,11 @@ impl Environment {
                     &config.age_and_death,
                     &config.education,
                     &config.wealth,
+                    rng,
                 )
             })
             .collect();
 
-        let mut rng = rand::thread_rng();
         let mut by_age: Vec<usize> = (0..agents.len()).collect();
         by_age.sort_by(|&a, &b| agents[a].age.cmp(&agents[b].age));
 
@@ -58,53 +123,56 @@ impl Environment {
                 0
             };
 
-            let possible_parents: Vec<usize> = by_age
-                .iter()
-                .filter(|&&idx| {
-                    agents[idx].age + 18 * 12 <= age && agents[idx].age + 45 * 12 > age && idx != i
-                })
-                .cloned()
-                .collect();
+            // Agents 18 to 45 years older, a contiguous run of `by_age`
+            let first = by_age.partition_point(|&idx| agents[idx].age + 45 * 12 <= age);
+            let end = by_age.partition_point(|&idx| agents[idx].age + 18 * 12 <= age);
+            let possible_parents = &by_age[first..end.max(first)];
             if possible_parents.len() >= num_parents {
                 let selected = possible_parents
-                    .choose_multiple(&mut rng, num_parents)
+                    .choose_multiple(rng, num_parents)
                     .cloned()
                     .collect::<Vec<_>>();
+                let child = agents[i].id();
                 for parent_idx in &selected {
-                    agents[*parent_idx].children.push(i);
+                    agents[*parent_idx].children.push(child);
                 }
             } else {
+                let child = agents[i].id();
                 possible_parents
                     .iter()
-                    .for_each(|&parent_idx| agents[parent_idx].children.push(i));
+                    .for_each(|&parent_idx| agents[parent_idx].children.push(child));
             }
         }
 
-        Self {
-            agents,
-            iteration: 0,
-            min_x: 0,
-            max_x: config.length,
-            min_y: 0,
-            max_y: config.width,
-            next_agent_id: config.num_agents,
-            config: config.clone(),
-            iteration_total_transaction_amount: 0.0,
-            iteration_total_transaction_count: 0,
+        AgentStore::new(agents)
+    }
+
+    pub fn run_simulation(
+        &mut self,
+        filepath: Option<&str>,
+        logging_enabled: bool,
+    ) -> Result<(), ConfigError> {
+        if logging_enabled {
+            let filepath = filepath.unwrap_or("visualisation/metrics.csv");
+            let metrics = Metrics::new(filepath).map_err(|source| ConfigError::Io {
+                path: filepath.to_string(),
+                source,
+            })?;
+            self.add_sink(Box::new(metrics));
         }
+        self.run()
     }
 
-    pub fn run_simulation(&mut self, filepath: Option<&str>, logging_enabled: bool) {
-        let metrics = Metrics::new(filepath.unwrap_or("visualisation/metrics.csv"));
-        while self.iteration < self.config.num_iterations {
-            self.step();
-            if logging_enabled {
-                metrics.log(
-                    self.iteration,
-                    &self.agents,
-                    self.iteration_total_transaction_amount,
-                    self.iteration_total_transaction_count,
-                );
+    /// Steps until `num_iterations` is reached or the run converges, feeding the
+    /// attached sinks. Stops early if the schedule makes the config invalid, and
+    /// fails if a sink cannot write what it recorded.
+    pub fn run(&mut self) -> Result<(), ConfigError> {
+        while !self.finished() {
+            if let Err(e) = self.step() {
+                if let Err(flush_error) = self.flush_sinks() {
+                    eprintln!("Warning: failed to write metrics: {}", flush_error);
+                }
+                return Err(e);
             }
 
             println!(
@@ -114,114 +182,308 @@ impl Environment {
                 self.iteration_total_transaction_count
             );
         }
+        self.finish().map_err(|source| ConfigError::Io {
+            path: "metrics".to_string(),
+            source,
+        })
+    }
+
+    /// Reports convergence and flushes the sinks once the run is finished,
+    /// returning the first error a sink has run into.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(iteration) = self.converged_at() {
+            println!("Converged at iteration {}", iteration);
+        }
+        self.flush_sinks()
+    }
+
+    pub fn config(&self) -> &EnvironmentConfig {
+        &self.config
+    }
+
+    /// Whether `num_iterations` has been reached or the convergence criteria are met.
+    pub fn finished(&self) -> bool {
+        self.iteration >= self.config.num_iterations || self.converged_at().is_some()
+    }
+
+    /// Iteration at which the convergence criteria were met, if they were.
+    pub fn converged_at(&self) -> Option<usize> {
+        self.convergence.as_ref().and_then(|c| c.converged_at())
+    }
+
+    /// Convergence iteration and steady-state averages, `None` without
+    /// convergence criteria in the config.
+    pub fn convergence_summary(&self) -> Option<ConvergenceSummary> {
+        self.convergence.as_ref().map(|c| c.summary(self.iteration))
     }
 
-    pub fn config(&self) -> EnvironmentConfig {
-        self.config
+    pub fn iteration(&self) -> usize {
+        self.iteration
     }
 
-    pub fn step(&mut self) {
+    pub fn agents(&self) -> &[Agent] {
+        &self.agents
+    }
+
+    /// Attaches a sink that receives the aggregate statistics after every step.
+    pub fn add_sink(&mut self, sink: Box<dyn MetricsSink + Send>) {
+        self.sinks.push(sink);
+    }
+
+    /// Saves a checkpoint to `path` every `every` iterations and when the run
+    /// finishes, replacing the previous one.
+    pub fn checkpoint_every(&mut self, path: &Path, every: usize) {
+        self.checkpoints = Some((path.to_path_buf(), every.max(1)));
+    }
+
+    /// Writes the full simulation state, including the RNG, so that
+    /// [`Environment::load_checkpoint`] continues with exactly the same trajectory.
+    pub fn save_checkpoint(&self, path: &Path) -> Result<(), ConfigError> {
+        checkpoint::save(self, path)
+    }
+
+    /// Restores a saved simulation. Sinks and checkpoint settings are not saved
+    /// and have to be set up again.
+    pub fn load_checkpoint(path: &Path) -> Result<Self, ConfigError> {
+        checkpoint::load(path)
+    }
+
+    /// Aggregate statistics of the current population, `None` if nobody is alive.
+    pub fn stats(&self) -> Option<Stats> {
+        Stats::compute(
+            self.iteration,
+            &self.agents,
+            self.iteration_total_transaction_amount,
+            self.iteration_total_transaction_count,
+            self.budget,
+        )
+    }
+
+    /// Advances the simulation by one iteration. Fails without changing the
+    /// agents if the schedule makes the config invalid.
+    pub fn step(&mut self) -> Result<(), ConfigError> {
+        self.apply_schedule()?;
+
         // Reset iteration totals
         self.iteration_total_transaction_amount = 0.0;
         self.iteration_total_transaction_count = 0;
+        self.budget.start_iteration();
+
+        let streams = AgentStreams::new(&self.config, self.iteration);
+        let (min_x, min_y) = (self.min_x as f64, self.min_y as f64);
+        let (max_x, max_y) = (self.max_x as f64, self.max_y as f64);
+        let config = &self.config;
+        // Collected in agent order and summed afterwards, so the total does not
+        // depend on how agents are split over threads
+        let income_taxes: Vec<f64> = self
+            .agents
+            .par_iter_mut()
+            .filter(|a| a.alive)
+            .map(|agent| {
+                let mut rng = streams.rng(agent.id(), Phase::Update);
+                agent.move_randomly(config.max_movement, min_x, min_y, max_x, max_y, &mut rng);
+
+                Environment::handle_learning(config, agent, &mut rng);
+                if agent.is_adult() {
+                    Environment::handle_income_and_consumption(config, agent)
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        self.budget.income_tax += income_taxes.iter().sum::<f64>();
+
+        self.handle_interactions();
+
+        self.update_agents();
+        self.agents.remove_dead();
+        self.run_budget();
+        self.iteration += 1;
+
+        self.publish_stats();
+        self.write_checkpoint();
+        Ok(())
+    }
 
-        let mut agents_with_parents: HashMap<usize, u32> = HashMap::new();
-        for agent in self.agents.iter() {
-            if !agent.children.is_empty() {
-                for child_id in &agent.children {
-                    *agents_with_parents.entry(*child_id).or_insert(0) += 1;
+    /// Saves a due checkpoint, after flushing the sinks so that the metrics
+    /// file covers every iteration the checkpoint does.
+    fn write_checkpoint(&mut self) {
+        if let Some((path, every)) = &self.checkpoints {
+            if self.iteration % *every == 0 || self.finished() {
+                let path = path.clone();
+                if let Err(e) = self.flush_sinks() {
+                    eprintln!(
+                        "Warning: skipped checkpoint as the metrics could not be written: {}",
+                        e
+                    );
+                } else if let Err(e) = self.save_checkpoint(&path) {
+                    eprintln!("Warning: failed to write checkpoint: {}", e);
                 }
             }
         }
+    }
 
-        for agent in self.agents.iter_mut().filter(|a| a.alive) {
-            agent.move_randomly(
-                self.config.max_movement,
-                self.min_x as f64,
-                self.min_y as f64,
-                self.max_x as f64,
-                self.max_y as f64,
-            );
+    /// Applies the schedule entries that change a field before the coming
+    /// iteration and checks the resulting config.
+    fn apply_schedule(&mut self) -> Result<(), ConfigError> {
+        let changes: Vec<_> = self
+            .config
+            .schedule
+            .iter()
+            .filter_map(|entry| {
+                entry
+                    .value_at(self.iteration, &self.config)
+                    .map(|value| (entry.path.clone(), value))
+            })
+            .collect();
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let mut config = self.config.clone();
+        for (path, value) in changes {
+            config.set_path(&path, value)?;
+        }
+        config.validate_fields()?;
+        self.config = config;
+        Ok(())
+    }
 
-            Environment::handle_learning(&self.config, agent);
-            if !agent.is_adult() {
-                continue;
+    /// Feeds the sinks that want this iteration and the convergence monitor,
+    /// computing the statistics at most once.
+    fn publish_stats(&mut self) {
+        let snapshot = || {
+            Snapshot::take(
+                self.iteration,
+                &self.agents,
+                self.iteration_total_transaction_amount,
+                self.iteration_total_transaction_count,
+                self.budget,
+            )
+        };
+        let mut stats = None;
+        for sink in self.sinks.iter_mut().filter(|s| s.wants(self.iteration)) {
+            if sink.takes_snapshots() {
+                sink.record_snapshot(snapshot());
+            } else if let Some(stats) =
+                stats.get_or_insert_with(|| Stats::from_snapshot(snapshot()))
+            {
+                sink.record(stats);
+            }
+        }
+        if let Some(convergence) = &mut self.convergence {
+            if let Some(stats) = stats.get_or_insert_with(|| Stats::from_snapshot(snapshot())) {
+                convergence.observe(stats);
             }
-            Environment::handle_income_and_consumption(&self.config, agent);
         }
+    }
 
-        self.handle_interactions();
+    /// Adds the taxes of this iteration to the government balance and pays out
+    /// its share of the balance. Without a government the taxes are lost.
+    fn run_budget(&mut self) {
+        let Some(government) = self.config.government else {
+            return;
+        };
+        self.budget.balance += self.budget.revenue();
+        self.budget.spending = government.redistribute(self.budget.balance, &mut self.agents);
+        self.budget.balance -= self.budget.spending;
+    }
 
-        self.update_agents();
-        self.agents.retain(|a| a.alive); // Remove dead agents
-        self.iteration += 1;
+    /// Flushes every sink, returning the first error.
+    fn flush_sinks(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for sink in self.sinks.iter_mut() {
+            let flushed = sink.flush();
+            if result.is_ok() {
+                result = flushed;
+            }
+        }
+        result
     }
 
     fn handle_interactions(&mut self) {
         // Select agents that are eligible for interaction based on age and interaction probability
+        let transaction_probability = self.config.transaction.transaction_probability;
+        let streams = AgentStreams::new(&self.config, self.iteration);
         let interaction_eligable_ids: Vec<usize> = self
             .agents
-            .iter()
+            .par_iter()
             .enumerate()
             .filter(|(_, a)| a.alive)
             .filter(|(_, a)| a.is_adult())
-            .filter(|(_, _)| {
-                rand::random::<f64>() < self.config.transaction.transaction_probability
+            .filter(|(_, a)| {
+                streams.rng(a.id(), Phase::Interaction).gen::<f64>() < transaction_probability
             })
             .map(|(i, _)| i)
             .collect();
 
         let tax_rate = self.config.transaction.tax_rate;
         let amount_rate = self.config.transaction.amount_rate;
+        let radius = self.config.interaction_radius;
 
-        for i in 0..interaction_eligable_ids.len() {
-            for j in (i + 1)..interaction_eligable_ids.len() {
-                let a_id = interaction_eligable_ids[i];
-                let b_id = interaction_eligable_ids[j];
-                let (ax, ay, bx, by);
-                {
-                    let a = &self.agents[a_id];
-                    let b = &self.agents[b_id];
-                    ax = a.x;
-                    ay = a.y;
-                    bx = b.x;
-                    by = b.y;
-                }
-                let dist = ((ax - bx).powi(2) + (ay - by).powi(2)).sqrt();
+        let positions: Vec<(f64, f64)> = interaction_eligable_ids
+            .iter()
+            .map(|&id| (self.agents[id].x, self.agents[id].y))
+            .collect();
+        let grid = SpatialGrid::new(&positions, self.max_x as f64, self.max_y as f64, radius);
+
+        // Neighbours are found in parallel, but pairs are then traded in the order
+        // of a scan over all pairs (i < j), as each trade changes the next ones
+        let neighbours: Vec<Vec<usize>> = (0..positions.len())
+            .into_par_iter()
+            .map(|i| {
+                let (ax, ay) = positions[i];
+                let mut neighbours = Vec::new();
+                grid.for_each_candidate(ax, ay, |j| {
+                    let (bx, by) = positions[j];
+                    if j > i && ((ax - bx).powi(2) + (ay - by).powi(2)).sqrt() < radius {
+                        neighbours.push(j);
+                    }
+                });
+                neighbours.sort_unstable();
+                neighbours
+            })
+            .collect();
 
-                if dist < self.config.interaction_radius {
-                    let (winner, loser) = self.decide_transaction_by_id(a_id, b_id);
-                    let amount = amount_rate * loser.wealth.min(winner.wealth);
-                    winner.wealth += (1.0 - tax_rate) * amount;
-                    loser.wealth -= amount;
+        for (i, neighbours) in neighbours.iter().enumerate() {
+            for &j in neighbours {
+                let (winner, loser) = self.decide_transaction_by_id(
+                    interaction_eligable_ids[i],
+                    interaction_eligable_ids[j],
+                );
+                let amount = amount_rate * loser.wealth.min(winner.wealth);
+                winner.wealth += (1.0 - tax_rate) * amount;
+                loser.wealth -= amount;
+                self.budget.transaction_tax += tax_rate * amount;
 
-                    self.iteration_total_transaction_amount += amount;
-                    self.iteration_total_transaction_count += 1;
-                }
+                self.iteration_total_transaction_amount += amount;
+                self.iteration_total_transaction_count += 1;
             }
         }
     }
 
-    fn handle_learning(config: &EnvironmentConfig, agent: &mut Agent) {
-        let learning_rate = rand::thread_rng()
-            .gen_range(config.education.learning_rate_min..config.education.learning_rate_max);
+    fn handle_learning(config: &EnvironmentConfig, agent: &mut Agent, rng: &mut impl Rng) {
+        let learning_rate =
+            rng.gen_range(config.education.learning_rate_min..config.education.learning_rate_max);
         let max_education = config.education.max;
         if agent.education < max_education {
             agent.education += learning_rate * (1.0 - agent.education / max_education);
         }
     }
 
-    fn handle_income_and_consumption(config: &EnvironmentConfig, agent: &mut Agent) {
+    /// Returns the income tax the agent paid.
+    fn handle_income_and_consumption(config: &EnvironmentConfig, agent: &mut Agent) -> f64 {
         let baseline_consumption = config.income_and_consumption.base_consumption;
-        let additional_consumption = config.income_and_consumption.aditional_consumption_rate;
+        let additional_consumption = config.income_and_consumption.additional_consumption_rate;
         let income_age_parameter = config.income_and_consumption.income_age_parameter;
         let income_education_parameter = config.income_and_consumption.income_education_parameter;
 
         let consumption = baseline_consumption
             + additional_consumption * (agent.wealth - baseline_consumption).max(0.0);
         let income = agent.income(income_education_parameter, income_age_parameter);
-        agent.wealth += income;
+        let income_tax = config.income_tax.as_ref().map_or(0.0, |t| t.tax(income));
+        agent.wealth += income - income_tax;
         agent.wealth -= consumption;
+        income_tax
     }
 
     fn decide_transaction_by_id(&mut self, a_id: usize, b_id: usize) -> (&mut Agent, &mut Agent) {
@@ -234,7 +496,7 @@ impl Environment {
             score_a = education_parameter * a.education + age_parameter * a.age as f64;
             score_b = education_parameter * b.education + age_parameter * b.age as f64;
         }
-        if rand::random::<f64>() < score_a / (score_a + score_b) {
+        if self.rng.gen::<f64>() < score_a / (score_a + score_b) {
             self.get_pair_mut(a_id, b_id)
         } else {
             self.get_pair_mut(b_id, a_id)
@@ -242,31 +504,37 @@ impl Environment {
     }
 
     fn get_pair_mut(&mut self, winner_id: usize, loser_id: usize) -> (&mut Agent, &mut Agent) {
-        let (left, right) = self.agents.split_at_mut(std::cmp::max(winner_id, loser_id));
-        if winner_id < loser_id {
-            (&mut left[winner_id], &mut right[0])
-        } else {
-            (&mut right[0], &mut left[loser_id])
-        }
+        self.agents.pair_mut(winner_id, loser_id)
     }
 
     fn update_agents(&mut self) {
         let size = self.agents.len();
-        let mut new_agents = Vec::new();
-        let mut inheritance: HashMap<usize, f64> = HashMap::new();
 
         let min_inheritance_at_birth_rate = self.config.wealth.min_inheritance_at_birth_rate;
         let max_inheritance_at_birth_rate = self.config.wealth.max_inheritance_at_birth_rate;
 
+        // Agents that can become parents, kept up to date as each agent ages
+        let mut reproductive =
+            IndexSet::new(self.agents.iter().map(Self::is_reproductive).collect());
+        let mut bequests = Vec::new();
+
         for i in 0..size {
-            if self.agents[i].alive && self.agents[i].age_and_check_death() {
-                self.resolve_inheritance(&mut inheritance, i);
+            if !self.agents[i].alive {
+                continue;
+            }
+            let died = self.agents[i].age_and_check_death(&mut self.rng);
+            reproductive.set(i, Self::is_reproductive(&self.agents[i]));
+            if died {
+                self.resolve_inheritance(i, size, &mut bequests);
+                // Deaths can leave too few adults to replace the agent
+                if reproductive.len() < 2 {
+                    continue;
+                }
 
                 // Create offspring (new agent) after inheritance logic
-                let id = self.next_agent_id;
-                self.next_agent_id += 1;
+                let id = self.agents.next_id();
                 let (min_x, max_x, min_y, max_y) = self.bounds();
-                let (p1, p2) = self.select_parents();
+                let (p1, p2, rng) = self.select_parents(&reproductive);
                 let child = Environment::create_offspring(
                     p1,
                     p2,
@@ -277,51 +545,71 @@ impl Environment {
                     max_y,
                     min_inheritance_at_birth_rate,
                     max_inheritance_at_birth_rate,
+                    rng,
                 );
-                new_agents.push(child);
+                self.agents.push(child);
             }
         }
+        self.pay_bequests(bequests);
+    }
 
-        for agent in self.agents.iter_mut() {
-            if let Some(amount) = inheritance.get(&agent.id) {
-                agent.wealth += amount;
-            }
+    /// Splits the wealth of the agent at `index` equally among its children that
+    /// were alive before this step (`size` agents), after estate tax, and adds
+    /// each share to `bequests`. Without such children the estate is lost.
+    fn resolve_inheritance(&mut self, index: usize, size: usize, bequests: &mut Vec<(usize, f64)>) {
+        let mut estate = std::mem::take(&mut self.agents[index].wealth);
+        if let Some(estate_tax) = &self.config.estate_tax {
+            let tax = estate_tax.tax(estate);
+            self.budget.estates += estate.max(0.0);
+            self.budget.estate_tax += tax;
+            estate -= tax;
         }
+        let heirs: Vec<usize> = self.agents[index]
+            .children
+            .iter()
+            .filter_map(|&child| self.agents.index_of(child))
+            .filter(|&heir| heir < size && self.agents[heir].alive)
+            .collect();
 
-        self.agents.extend(new_agents);
+        if !heirs.is_empty() {
+            let share = estate / heirs.len() as f64;
+            bequests.extend(heirs.into_iter().map(|heir| (heir, share)));
+        }
     }
 
-    fn resolve_inheritance(
-        &mut self,
-        inheritance: &mut HashMap<usize, f64>,
-        agent_id: usize,
-    ) -> () {
-        let agent = &mut self.agents[agent_id];
-        let dead_agent_wealth = agent.wealth;
-        let children_ids = agent.children.clone();
-        let num_children = children_ids.len();
-
-        if num_children > 0 {
-            let share = dead_agent_wealth / num_children as f64;
-            for child_id in children_ids {
-                *inheritance.entry(child_id).or_insert(0.0) += share;
+    /// Pays the bequests of a step once every agent has aged, so that children
+    /// born during the step inherit nothing and parents cannot pass on in the
+    /// same step what they inherited. Shares of heirs that died since are lost.
+    fn pay_bequests(&mut self, bequests: Vec<(usize, f64)>) {
+        for (heir, share) in bequests {
+            let heir = &mut self.agents[heir];
+            if !heir.alive {
+                continue;
+            }
+            let mut received = share;
+            if share > 0.0 {
+                if let Some(inheritance_tax) = &self.config.inheritance_tax {
+                    let tax = inheritance_tax.tax(heir.inherited, share);
+                    self.budget.inheritances += share;
+                    self.budget.inheritance_tax += tax;
+                    received -= tax;
+                }
+                heir.inherited += share;
             }
+            heir.wealth += received;
         }
-
-        agent.wealth = 0.0;
     }
 
-    fn select_parents(&mut self) -> (&mut Agent, &mut Agent) {
-        let mut rng = rand::thread_rng();
-        let reproductive_indices: Vec<_> = self
-            .agents
-            .iter()
-            .enumerate()
-            .filter(|(_, a)| a.age > 20 * 12 && a.age < 60 * 12 && a.alive)
-            .map(|(i, _)| i)
-            .collect();
+    fn is_reproductive(agent: &Agent) -> bool {
+        agent.age > 20 * 12 && agent.age < 60 * 12 && agent.alive
+    }
 
-        let len = reproductive_indices.len();
+    fn select_parents(
+        &mut self,
+        reproductive: &IndexSet,
+    ) -> (&mut Agent, &mut Agent, &mut ChaCha8Rng) {
+        let rng = &mut self.rng;
+        let len = reproductive.len();
         assert!(len >= 2, "Not enough agents to select parents");
 
         let idx1 = rng.gen_range(0..len);
@@ -330,31 +618,26 @@ impl Environment {
             idx2 = rng.gen_range(0..len);
         }
 
-        let a_idx = reproductive_indices[idx1];
-        let b_idx = reproductive_indices[idx2];
+        let a_idx = reproductive.nth(idx1);
+        let b_idx = reproductive.nth(idx2);
 
-        let (first, second) = if a_idx < b_idx {
-            let (left, right) = self.agents.split_at_mut(b_idx);
-            (&mut left[a_idx], &mut right[0])
-        } else {
-            let (left, right) = self.agents.split_at_mut(a_idx);
-            (&mut right[0], &mut left[b_idx])
-        };
-        (first, second)
+        let (first, second) = self.agents.pair_mut(a_idx, b_idx);
+        (first, second, rng)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn create_offspring(
         p1: &mut Agent,
         p2: &mut Agent,
-        id: usize,
+        id: AgentId,
         min_x: usize,
         max_x: usize,
         min_y: usize,
         max_y: usize,
         min_inheritance_at_birth_rate: f64,
         max_inheritance_at_birth_rate: f64,
+        rng: &mut impl Rng,
     ) -> Agent {
-        let mut rng = rand::thread_rng();
         let parent1_inheritance =
             p1.wealth * rng.gen_range(min_inheritance_at_birth_rate..max_inheritance_at_birth_rate);
         let parent2_inheritance =
@@ -365,21 +648,192 @@ impl Environment {
         p1.children.push(id);
         p2.children.push(id);
 
-        Agent {
+        let mut child = Agent::newborn(
             id,
-            children: Vec::new(),
-            x: rng.gen_range(min_x as f64..max_x as f64),
-            y: rng.gen_range(min_y as f64..max_y as f64),
-            wealth,
-            education: 0.0,
-            age: 0,
-            alive: true,
-            mid_age: p1.mid_age,
-            steepness: p1.steepness,
-        }
+            rng.gen_range(min_x as f64..max_x as f64),
+            rng.gen_range(min_y as f64..max_y as f64),
+            p1.mid_age,
+            p1.steepness,
+        );
+        child.wealth = wealth;
+        child
     }
 
     pub fn bounds(&self) -> (usize, usize, usize, usize) {
         (self.min_x, self.max_x, self.min_y, self.max_y)
     }
 }
+
+/// Per-agent phases of an iteration that draw from the agents' own streams.
+#[derive(Clone, Copy)]
+enum Phase {
+    Update = 0,      // movement and learning
+    Interaction = 1, // whether the agent trades this iteration
+}
+
+/// Independent random streams for every agent, derived from the run seed, the
+/// agent id, the iteration and the phase only. Agents can therefore be updated
+/// in any order and on any number of threads with the same results.
+struct AgentStreams {
+    base: ChaCha8Rng,
+    iteration: u128,
+}
+
+impl AgentStreams {
+    /// 32-bit words reserved for each agent, iteration and phase; a phase uses
+    /// only a handful of them.
+    const WORDS_PER_PHASE: u128 = 1 << 8;
+
+    fn new(config: &EnvironmentConfig, iteration: usize) -> Self {
+        let seed = config.seed.expect("Environment::try_new sets the seed");
+        Self {
+            base: ChaCha8Rng::seed_from_u64(seed),
+            iteration: iteration as u128,
+        }
+    }
+
+    fn rng(&self, agent_id: AgentId, phase: Phase) -> ChaCha8Rng {
+        let mut rng = self.base.clone();
+        // Stream 0 is the environment's own generator
+        rng.set_stream(agent_id.0 as u64 + 1);
+        rng.set_word_pos((self.iteration * 2 + phase as u128) * Self::WORDS_PER_PHASE);
+        rng
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::taxation::{EstateTax, InheritanceTax, TaxBracket, TaxRates};
+
+    fn adult(id: usize, wealth: f64, inherited: f64) -> Agent {
+        let mut agent = Agent::newborn(AgentId(id), 0.0, 0.0, 80.0, 0.1);
+        agent.age = 40 * 12;
+        agent.wealth = wealth;
+        agent.inherited = inherited;
+        agent
+    }
+
+    #[test]
+    fn estates_are_taxed_before_the_heirs_pay_inheritance_tax() {
+        let mut config = EnvironmentConfig {
+            num_agents: 4,
+            seed: Some(1),
+            ..EnvironmentConfig::default()
+        };
+        config.estate_tax = Some(EstateTax {
+            exemption: 100.0,
+            rates: TaxRates::Flat(0.2),
+        });
+        config.inheritance_tax = Some(InheritanceTax {
+            exemption: 50.0,
+            rates: TaxRates::Brackets(vec![
+                TaxBracket {
+                    threshold: 0.0,
+                    rate: 0.1,
+                },
+                TaxBracket {
+                    threshold: 100.0,
+                    rate: 0.3,
+                },
+            ]),
+        });
+        let mut env = Environment::try_new(&config).unwrap();
+
+        let mut parent = adult(0, 300.0, 0.0);
+        parent.children = vec![AgentId(1), AgentId(2), AgentId(3)];
+        let mut dead_child = adult(3, 5.0, 0.0);
+        dead_child.alive = false;
+        env.agents = AgentStore::new(vec![
+            parent,
+            adult(1, 10.0, 0.0),
+            adult(2, 20.0, 100.0),
+            dead_child,
+        ]);
+        let mut bequests = Vec::new();
+        env.resolve_inheritance(0, 4, &mut bequests);
+        env.pay_bequests(bequests);
+
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        // 40 estate tax on 300, leaving 130 for each living child
+        assert_eq!(env.agents[0].wealth, 0.0);
+        assert!(close(env.budget.estate_tax, 40.0));
+        assert!(close(env.budget.estate_tax_rate(), 40.0 / 300.0));
+        // 8 on the first child's 130 and 29 on the second's, who already inherited 100
+        assert!(close(env.agents[1].wealth, 10.0 + 130.0 - 8.0));
+        assert!(close(env.agents[2].wealth, 20.0 + 130.0 - 29.0));
+        assert!(close(env.agents[1].inherited, 130.0));
+        assert!(close(env.agents[2].inherited, 230.0));
+        assert_eq!(env.agents[3].wealth, 5.0);
+        assert!(close(env.budget.inheritance_tax, 37.0));
+        assert!(close(env.budget.inheritance_tax_rate(), 37.0 / 260.0));
+        assert!(close(env.budget.revenue(), 77.0));
+    }
+
+    #[test]
+    fn children_born_during_a_step_inherit_nothing_in_it() {
+        let mut env = Environment::try_new(&EnvironmentConfig {
+            num_agents: 4,
+            seed: Some(1),
+            ..EnvironmentConfig::default()
+        })
+        .unwrap();
+        let dying = |id: usize, wealth: f64| {
+            let mut agent = Agent::newborn(AgentId(id), 0.0, 0.0, 0.0, 10.0);
+            agent.age = 70 * 12;
+            agent.wealth = wealth;
+            agent
+        };
+        // The first death gives the two adults child 4; the second leaves its
+        // estate to that child, who was not alive when the step began
+        let mut estate = dying(3, 100.0);
+        estate.children = vec![AgentId(4)];
+        env.agents = AgentStore::new(vec![
+            dying(0, 0.0),
+            adult(1, 0.0, 0.0),
+            adult(2, 0.0, 0.0),
+            estate,
+        ]);
+        env.update_agents();
+
+        let child = env.agents.get(AgentId(4)).unwrap();
+        assert_eq!(child.age, 0);
+        assert_eq!(child.wealth, 0.0);
+        assert_eq!(child.inherited, 0.0);
+        assert_eq!(env.agents[3].wealth, 0.0);
+    }
+
+    #[test]
+    fn deaths_without_two_adults_left_are_not_replaced() {
+        let mut env = Environment::try_new(&EnvironmentConfig {
+            num_agents: 3,
+            seed: Some(1),
+            ..EnvironmentConfig::default()
+        })
+        .unwrap();
+        let mut agents: Vec<Agent> = (0..3).map(|id| adult(id, 10.0, 0.0)).collect();
+        for agent in &mut agents[1..] {
+            agent.mid_age = 0.0;
+            agent.steepness = 10.0;
+        }
+        env.agents = AgentStore::new(agents);
+        // The first death is replaced by a child of agents 0 and 2, after which
+        // agent 0 is the only adult left when agent 2 dies
+        env.step().unwrap();
+        let ids: Vec<AgentId> = env.agents.iter().map(|a| a.id()).collect();
+        assert_eq!(ids, vec![AgentId(0), AgentId(3)]);
+        env.step().unwrap();
+    }
+
+    #[test]
+    fn effective_rates_are_zero_without_estates() {
+        let env = Environment::try_new(&EnvironmentConfig {
+            num_agents: 4,
+            seed: Some(1),
+            ..EnvironmentConfig::default()
+        })
+        .unwrap();
+        assert_eq!(env.budget.estate_tax_rate(), 0.0);
+        assert_eq!(env.budget.inheritance_tax_rate(), 0.0);
+    }
+}
@@ -1,65 +1,174 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::{Read, Write};
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+use crate::config_error::{ConfigError, ValidationIssue};
+use crate::config_file;
+use crate::config_migration::CURRENT_SCHEMA_VERSION;
+use crate::convergence::Convergence;
+use crate::government::Government;
+use crate::schedule::{self, ScheduleEntry};
+use crate::taxation::{EstateTax, IncomeTax, InheritanceTax};
+use std::path::Path;
+
+/// Initial age distribution and the logistic monthly death probability.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy)]
 pub struct AgeAndDeath {
+    /// Mean age in years. Not used by the current model.
+    #[schemars(range(min = 0.0))]
     pub mean_age: f64,
+    /// Standard deviation of age in years. Not used by the current model.
+    #[schemars(range(min = 0.0))]
     pub stddev_age: f64,
-    pub mid_age: f64, // age where death chance is 50%
+    /// Age in years where the monthly death chance is 50%.
+    #[schemars(extend("exclusiveMinimum" = 0.0))]
+    pub mid_age: f64,
+    /// Initial ages are drawn uniformly from [0, max_start_age) years.
+    #[schemars(extend("exclusiveMinimum" = 30.0))]
     pub max_start_age: f64,
-    pub steepness: f64, // how quickly death probability rises with age
+    /// How quickly the death probability rises with age, per month.
+    #[schemars(extend("exclusiveMinimum" = 0.0))]
+    pub steepness: f64,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+/// Initial education levels and yearly learning.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy)]
 pub struct Education {
+    /// Lower bound of the initial education of adults. Must not exceed `initial_adult_max`.
+    #[schemars(range(min = 0.0))]
     pub initial_adult_min: f64,
+    /// Upper bound of the initial education of adults.
+    #[schemars(range(min = 0.0))]
     pub initial_adult_max: f64,
+    /// Education an initial child reaches by 18, growing linearly from age 6.
+    #[schemars(range(min = 0.0))]
     pub elemental_education_threshold: f64,
+    /// Upper bound of the random education added to initial children.
+    #[schemars(extend("exclusiveMinimum" = 0.0))]
     pub children_education_jitter: f64,
+    /// Lower bound of the monthly learning rate. Must be less than `learning_rate_max`.
+    #[schemars(range(min = 0.0))]
     pub learning_rate_min: f64,
+    /// Upper bound of the monthly learning rate.
+    #[schemars(range(min = 0.0))]
     pub learning_rate_max: f64,
+    /// Education level learning converges to.
+    #[schemars(extend("exclusiveMinimum" = 0.0))]
     pub max: f64,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+/// Monthly income and consumption of adults.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy)]
 pub struct IncomeAndConsumption {
+    /// Income per month of age.
     pub income_age_parameter: f64,
+    /// Income per unit of education.
     pub income_education_parameter: f64,
+    /// Fixed monthly consumption.
+    #[schemars(range(min = 0.0))]
     pub base_consumption: f64,
-    pub aditional_consumption_rate: f64,
+    /// Share of the wealth above `base_consumption` consumed each month.
+    #[serde(alias = "aditional_consumption_rate")]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub additional_consumption_rate: f64,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+/// Pairwise wealth transfers between nearby adults.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy)]
 pub struct Transaction {
+    /// Probability that an adult takes part in transactions in a given month.
+    #[schemars(range(min = 0.0, max = 1.0))]
     pub transaction_probability: f64,
+    /// Weight of education in the chance of winning a transaction.
+    #[schemars(range(min = 0.0))]
     pub education_parameter: f64,
+    /// Weight of age in months in the chance of winning a transaction.
+    #[schemars(range(min = 0.0))]
     pub age_parameter: f64,
+    /// Share of each transferred amount taken as tax.
+    #[schemars(range(min = 0.0, max = 1.0))]
     pub tax_rate: f64,
+    /// Share of the poorer party's wealth transferred in a transaction.
+    #[schemars(range(min = 0.0, max = 1.0))]
     pub amount_rate: f64,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+/// Initial wealth and the wealth parents give to newborns.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy)]
 pub struct Wealth {
+    /// Lower bound of the initial wealth. Must be less than `max_initial_wealth`.
+    #[schemars(range(min = 0.0))]
     pub min_initial_wealth: f64,
+    /// Upper bound of the initial wealth.
     pub max_initial_wealth: f64,
+    /// Lower bound of the share of each parent's wealth given to a newborn.
+    /// Must be less than `max_inheritance_at_birth_rate`.
+    #[schemars(range(min = 0.0, max = 1.0))]
     pub min_inheritance_at_birth_rate: f64,
+    /// Upper bound of the share of each parent's wealth given to a newborn.
+    #[schemars(range(min = 0.0, max = 1.0))]
     pub max_inheritance_at_birth_rate: f64,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+/// Parameters of one simulation run. One iteration is one month.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
 pub struct EnvironmentConfig {
+    /// Config schema version; files without it are version 1 and get migrated.
+    #[serde(default = "legacy_schema_version")]
+    #[schemars(transform = current_schema_version)]
+    pub schema_version: u32,
+    /// Number of iterations (months) to simulate.
+    #[schemars(range(min = 1))]
     pub num_iterations: usize,
+    /// Initial number of agents. The population size stays constant.
+    #[schemars(range(min = 2))]
     pub num_agents: usize,
+    /// Size of the world along x.
+    #[schemars(range(min = 1))]
     pub length: usize,
+    /// Size of the world along y.
+    #[schemars(range(min = 1))]
     pub width: usize,
+    /// Maximum distance between two agents that can transact.
+    #[schemars(range(min = 0.0))]
     pub interaction_radius: f64,
+    /// Maximum distance an agent moves in one iteration.
+    #[schemars(extend("exclusiveMinimum" = 0.0))]
     pub max_movement: f64,
     pub age_and_death: AgeAndDeath,
     pub education: Education,
     pub income_and_consumption: IncomeAndConsumption,
     pub transaction: Transaction,
     pub wealth: Wealth,
+    /// Progressive tax on the income of adults, collected monthly.
+    #[serde(default)]
+    pub income_tax: Option<IncomeTax>,
+    /// Tax on estates before they are split among the heirs.
+    #[serde(default)]
+    pub estate_tax: Option<EstateTax>,
+    /// Tax heirs pay on their lifetime inheritance.
+    #[serde(default)]
+    pub inheritance_tax: Option<InheritanceTax>,
+    /// Keep the collected taxes and redistribute them; without it they are lost.
+    #[serde(default)]
+    pub government: Option<Government>,
+    /// Fixed seed for reproducible runs, random if absent.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Number of seeded copies of the run. Above 1, replicate `i` uses seed
+    /// `seed + i` and the metrics are aggregated across replicates.
+    #[serde(default = "single_replicate")]
+    #[schemars(range(min = 1))]
+    pub replicates: usize,
+    /// CSV, JSON or YAML file of agents to start from instead of a random
+    /// population; `num_agents` is then taken from the file.
+    #[serde(default)]
+    pub initial_population: Option<String>,
+    /// Stop before `num_iterations` once the selected metrics are steady.
+    #[serde(default)]
+    pub convergence: Option<Convergence>,
+    /// Changes of config fields during the run, applied in order before each iteration.
+    #[serde(default)]
+    pub schedule: Vec<ScheduleEntry>,
 }
 
 impl AgeAndDeath {
@@ -81,6 +190,7 @@ impl AgeAndDeath {
 }
 
 impl Education {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         initial_adult_min: f64,
         initial_adult_max: f64,
@@ -107,13 +217,13 @@ impl IncomeAndConsumption {
         income_age_parameter: f64,
         income_education_parameter: f64,
         base_consumption: f64,
-        aditional_consumption_rate: f64,
+        additional_consumption_rate: f64,
     ) -> Self {
         Self {
             income_age_parameter,
             income_education_parameter,
             base_consumption,
-            aditional_consumption_rate,
+            additional_consumption_rate,
         }
     }
 }
@@ -153,6 +263,7 @@ impl Wealth {
 }
 
 impl EnvironmentConfig {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         num_iterations: usize,
         num_agents: usize,
@@ -165,8 +276,10 @@ impl EnvironmentConfig {
         income_and_consumption: IncomeAndConsumption,
         transaction: Transaction,
         wealth: Wealth,
+        seed: Option<u64>,
     ) -> Self {
         Self {
+            schema_version: CURRENT_SCHEMA_VERSION,
             num_iterations,
             num_agents,
             length,
@@ -178,20 +291,351 @@ impl EnvironmentConfig {
             income_and_consumption,
             transaction,
             wealth,
+            income_tax: None,
+            estate_tax: None,
+            inheritance_tax: None,
+            government: None,
+            seed,
+            replicates: 1,
+            initial_population: None,
+            convergence: None,
+            schedule: Vec::new(),
+        }
+    }
+
+    pub fn load_from_file(path: &str) -> Result<Self, ConfigError> {
+        let config = Self::read_from_file(path)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parses a JSON, TOML or YAML config file (by extension), following its `extends` chain, without validating it,
+    /// so that callers can adjust fields before running [`EnvironmentConfig::validate`].
+    pub fn read_from_file(path: &str) -> Result<Self, ConfigError> {
+        Ok(Self::read_from_file_with_warnings(path)?.0)
+    }
+
+    /// Like [`EnvironmentConfig::read_from_file`], but also returns a warning for
+    /// every deprecated field that was upgraded to the current schema, for the
+    /// caller to show.
+    pub fn read_from_file_with_warnings(path: &str) -> Result<(Self, Vec<String>), ConfigError> {
+        let (value, warnings) = config_file::read_layered(Path::new(path))?;
+        let config = serde_json::from_value(value).map_err(|e| ConfigError::Parse {
+            path: path.to_string(),
+            message: e.to_string(),
+        })?;
+        Ok((config, warnings))
+    }
+
+    /// JSON Schema describing every field, its type and its valid range.
+    pub fn json_schema() -> serde_json::Value {
+        schemars::schema_for!(EnvironmentConfig).to_value()
+    }
+
+    /// Upgrades a config file to the current schema version in place and returns
+    /// warnings about the deprecated fields that were rewritten, or `None` if the
+    /// file already is in the current schema. Comments in TOML and YAML files are kept.
+    pub fn migrate_file(path: &str) -> Result<Option<Vec<String>>, ConfigError> {
+        config_file::migrate_in_place(Path::new(path))
+    }
+
+    /// Saves the config as JSON, TOML or YAML depending on the file extension.
+    pub fn save_to_file(&self, path: &str) -> Result<(), ConfigError> {
+        config_file::write(Path::new(path), self)
+    }
+
+    /// Value of the field at a dotted path such as `transaction.tax_rate`.
+    pub fn get_path(&self, path: &str) -> Result<serde_json::Value, ConfigError> {
+        let mut root = serde_json::to_value(self).expect("Failed to serialize config");
+        Ok(Self::field_mut(&mut root, path)?.take())
+    }
+
+    /// Sets the field at a dotted path such as `transaction.tax_rate` to `value`.
+    /// The path must name an existing field and the value must fit its type.
+    pub fn set_path(&mut self, path: &str, value: serde_json::Value) -> Result<(), ConfigError> {
+        let override_error = |message: String| ConfigError::Override {
+            path: path.to_string(),
+            message,
+        };
+
+        let mut root = serde_json::to_value(&*self).expect("Failed to serialize config");
+        let target = Self::field_mut(&mut root, path)?;
+        if target.is_object() {
+            return Err(override_error(
+                "is a section, set one of its fields instead".to_string(),
+            ));
         }
+        *target = value;
+
+        *self = serde_json::from_value(root).map_err(|e| override_error(e.to_string()))?;
+        Ok(())
+    }
+
+    pub(crate) fn field_mut<'a>(
+        root: &'a mut serde_json::Value,
+        path: &str,
+    ) -> Result<&'a mut serde_json::Value, ConfigError> {
+        let mut target = root;
+        for key in path.split('.') {
+            target = target
+                .as_object_mut()
+                .and_then(|object| object.get_mut(key))
+                .ok_or_else(|| ConfigError::Override {
+                    path: path.to_string(),
+                    message: "no such field".to_string(),
+                })?;
+        }
+        Ok(target)
+    }
+
+    /// Applies a `path=value` assignment, e.g. `transaction.tax_rate=0.1`. The value
+    /// is read as JSON, so numbers, booleans and `null` keep their type.
+    pub fn apply_assignment(&mut self, assignment: &str) -> Result<(), ConfigError> {
+        let (path, raw) = assignment
+            .split_once('=')
+            .ok_or_else(|| ConfigError::Override {
+                path: assignment.to_string(),
+                message: "expected <path>=<value>".to_string(),
+            })?;
+        let value = serde_json::from_str(raw.trim())
+            .unwrap_or_else(|_| serde_json::Value::String(raw.trim().to_string()));
+        self.set_path(path.trim(), value)
     }
-    pub fn load_from_file(path: &str) -> Self {
-        let mut file = File::open(path).expect("Failed to open config file");
-        let mut content = String::new();
-        file.read_to_string(&mut content)
-            .expect("Failed to read config");
-        serde_json::from_str(&content).expect("Failed to deserialize config")
+
+    /// Checks every field against the constraints the simulation relies on,
+    /// including the configs the schedule builds up during the run, and reports
+    /// all violations at once.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut issues = self.field_issues();
+        let entry_issues = issues.len();
+        for (i, entry) in self.schedule.iter().enumerate() {
+            entry.validate(&format!("schedule.{}", i), self, &mut issues);
+        }
+        // The timeline can only be replayed once every entry fits its field
+        if issues.len() == entry_issues {
+            schedule::validate_timeline(self, &mut issues);
+        }
+        Validator { issues }.finish()
     }
 
-    pub fn save_to_file(&self, path: &str) {
-        let json = serde_json::to_string_pretty(self).expect("Failed to serialize config");
-        let mut file = File::create(path).expect("Failed to create config file");
-        file.write_all(json.as_bytes())
-            .expect("Failed to write config");
+    /// Checks every field but the schedule entries themselves.
+    pub(crate) fn validate_fields(&self) -> Result<(), ConfigError> {
+        Validator {
+            issues: self.field_issues(),
+        }
+        .finish()
+    }
+
+    fn field_issues(&self) -> Vec<ValidationIssue> {
+        let mut v = Validator::default();
+
+        v.check(
+            "schema_version",
+            self.schema_version == CURRENT_SCHEMA_VERSION,
+            &format!("must be {}", CURRENT_SCHEMA_VERSION),
+        );
+
+        v.check(
+            "num_iterations",
+            self.num_iterations > 0,
+            "must be greater than 0",
+        );
+        v.check("num_agents", self.num_agents >= 2, "must be at least 2");
+        v.check("replicates", self.replicates >= 1, "must be at least 1");
+        v.check("length", self.length > 0, "must be greater than 0");
+        v.check("width", self.width > 0, "must be greater than 0");
+        v.non_negative("interaction_radius", self.interaction_radius);
+        v.positive("max_movement", self.max_movement);
+
+        let a = &self.age_and_death;
+        v.non_negative("age_and_death.mean_age", a.mean_age);
+        v.non_negative("age_and_death.stddev_age", a.stddev_age);
+        v.positive("age_and_death.mid_age", a.mid_age);
+        v.check(
+            "age_and_death.max_start_age",
+            a.max_start_age.is_finite() && a.max_start_age > 30.0,
+            "must be greater than 30",
+        );
+        v.positive("age_and_death.steepness", a.steepness);
+
+        let e = &self.education;
+        v.non_negative("education.initial_adult_min", e.initial_adult_min);
+        v.non_negative("education.initial_adult_max", e.initial_adult_max);
+        v.check(
+            "education.initial_adult_min",
+            e.initial_adult_min <= e.initial_adult_max,
+            "must not exceed education.initial_adult_max",
+        );
+        v.non_negative(
+            "education.elemental_education_threshold",
+            e.elemental_education_threshold,
+        );
+        v.positive(
+            "education.children_education_jitter",
+            e.children_education_jitter,
+        );
+        v.non_negative("education.learning_rate_min", e.learning_rate_min);
+        v.non_negative("education.learning_rate_max", e.learning_rate_max);
+        v.check(
+            "education.learning_rate_min",
+            e.learning_rate_min < e.learning_rate_max,
+            "must be less than education.learning_rate_max",
+        );
+        v.positive("education.max", e.max);
+
+        let i = &self.income_and_consumption;
+        v.finite(
+            "income_and_consumption.income_age_parameter",
+            i.income_age_parameter,
+        );
+        v.finite(
+            "income_and_consumption.income_education_parameter",
+            i.income_education_parameter,
+        );
+        v.non_negative(
+            "income_and_consumption.base_consumption",
+            i.base_consumption,
+        );
+        v.unit_interval(
+            "income_and_consumption.additional_consumption_rate",
+            i.additional_consumption_rate,
+        );
+
+        let t = &self.transaction;
+        v.unit_interval(
+            "transaction.transaction_probability",
+            t.transaction_probability,
+        );
+        v.non_negative("transaction.education_parameter", t.education_parameter);
+        v.non_negative("transaction.age_parameter", t.age_parameter);
+        v.unit_interval("transaction.tax_rate", t.tax_rate);
+        v.unit_interval("transaction.amount_rate", t.amount_rate);
+
+        let w = &self.wealth;
+        v.non_negative("wealth.min_initial_wealth", w.min_initial_wealth);
+        v.finite("wealth.max_initial_wealth", w.max_initial_wealth);
+        v.check(
+            "wealth.min_initial_wealth",
+            w.min_initial_wealth < w.max_initial_wealth,
+            "must be less than wealth.max_initial_wealth",
+        );
+        v.unit_interval(
+            "wealth.min_inheritance_at_birth_rate",
+            w.min_inheritance_at_birth_rate,
+        );
+        v.unit_interval(
+            "wealth.max_inheritance_at_birth_rate",
+            w.max_inheritance_at_birth_rate,
+        );
+        v.check(
+            "wealth.min_inheritance_at_birth_rate",
+            w.min_inheritance_at_birth_rate < w.max_inheritance_at_birth_rate,
+            "must be less than wealth.max_inheritance_at_birth_rate",
+        );
+
+        if let Some(income_tax) = &self.income_tax {
+            income_tax.validate("income_tax", &mut v.issues);
+        }
+        if let Some(estate_tax) = &self.estate_tax {
+            estate_tax.validate("estate_tax", &mut v.issues);
+        }
+        if let Some(inheritance_tax) = &self.inheritance_tax {
+            inheritance_tax.validate("inheritance_tax", &mut v.issues);
+        }
+        if let Some(government) = &self.government {
+            government.validate("government", &mut v.issues);
+        }
+        if let Some(convergence) = &self.convergence {
+            convergence.validate("convergence", &mut v.issues);
+        }
+        v.issues
+    }
+}
+
+fn legacy_schema_version() -> u32 {
+    1
+}
+
+/// Files written against the schema are in the current version, which is the
+/// only one that validates; older files are migrated when loaded.
+fn current_schema_version(schema: &mut schemars::Schema) {
+    schema.insert("const".to_string(), CURRENT_SCHEMA_VERSION.into());
+    schema.remove("minimum");
+    schema.remove("default");
+}
+
+fn single_replicate() -> usize {
+    1
+}
+
+#[derive(Default)]
+struct Validator {
+    issues: Vec<ValidationIssue>,
+}
+
+impl Validator {
+    fn check(&mut self, path: &str, ok: bool, constraint: &str) {
+        if !ok {
+            self.issues.push(ValidationIssue {
+                path: path.to_string(),
+                constraint: constraint.to_string(),
+            });
+        }
+    }
+
+    fn finite(&mut self, path: &str, value: f64) {
+        self.check(path, value.is_finite(), "must be a finite number");
+    }
+
+    fn positive(&mut self, path: &str, value: f64) {
+        self.check(
+            path,
+            value.is_finite() && value > 0.0,
+            "must be greater than 0",
+        );
+    }
+
+    fn non_negative(&mut self, path: &str, value: f64) {
+        self.check(
+            path,
+            value.is_finite() && value >= 0.0,
+            "must be at least 0",
+        );
+    }
+
+    fn unit_interval(&mut self, path: &str, value: f64) {
+        self.check(path, (0.0..=1.0).contains(&value), "must be within [0, 1]");
+    }
+
+    fn finish(self) -> Result<(), ConfigError> {
+        if self.issues.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(self.issues))
+        }
+    }
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        let age_and_death = AgeAndDeath::new(30.0, 10.0, 80.0, 90.0, 0.02);
+        let education = Education::new(4.0, 10.0, 4.0, 2.0, 0.005, 0.05, 10.0);
+        let income_and_consumption = IncomeAndConsumption::new(0.05, 2.0, 10.0, 0.2);
+        let transaction = Transaction::new(0.3, 1.0, 0.001, 0.05, 0.05);
+        let wealth = Wealth::new(10.0, 100.0, 0.1, 0.3);
+        Self::new(
+            5000,
+            1000,
+            1000,
+            1000,
+            50.0,
+            15.0,
+            age_and_death,
+            education,
+            income_and_consumption,
+            transaction,
+            wealth,
+            None,
+        )
     }
 }
@@ -0,0 +1,273 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::agent::Agent;
+use crate::agent_store::AgentStore;
+use crate::config_error::ValidationIssue;
+
+/// How the government pays out its budget.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Debug)]
+#[serde(tag = "scheme", rename_all = "snake_case")]
+pub enum Redistribution {
+    /// Equal transfer to every living adult.
+    BasicIncome,
+    /// Equal transfer to the poorest `bottom_share` of living adults.
+    MeansTested {
+        #[schemars(extend("exclusiveMinimum" = 0.0), range(max = 1.0))]
+        bottom_share: f64,
+    },
+    /// Transfer to every adult in proportion to their living children under 18.
+    PerChild,
+}
+
+/// Government account that keeps the collected taxes and pays them back out.
+/// Without it, taxes leave the economy.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug)]
+pub struct Government {
+    pub redistribution: Redistribution,
+    /// Share of the balance paid out each month.
+    #[serde(default = "full_payout")]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub payout_rate: f64,
+}
+
+/// Government account balance and its flows in the current iteration.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug)]
+pub struct Budget {
+    pub balance: f64,
+    pub transaction_tax: f64,
+    pub income_tax: f64,
+    pub estate_tax: f64,
+    pub inheritance_tax: f64,
+    pub spending: f64,
+    /// Taxed estates before estate tax, the base of its effective rate.
+    pub estates: f64,
+    /// Taxed inheritances before inheritance tax, the base of its effective rate.
+    pub inheritances: f64,
+}
+
+impl Government {
+    /// Reports issues under `prefix`, e.g. `government.payout_rate`.
+    pub(crate) fn validate(&self, prefix: &str, issues: &mut Vec<ValidationIssue>) {
+        let mut check = |ok: bool, field: &str, constraint: &str| {
+            if !ok {
+                issues.push(ValidationIssue {
+                    path: format!("{}.{}", prefix, field),
+                    constraint: constraint.to_string(),
+                });
+            }
+        };
+        check(
+            (0.0..=1.0).contains(&self.payout_rate),
+            "payout_rate",
+            "must be within [0, 1]",
+        );
+        if let Redistribution::MeansTested { bottom_share } = self.redistribution {
+            check(
+                bottom_share > 0.0 && bottom_share <= 1.0,
+                "redistribution.bottom_share",
+                "must be within (0, 1]",
+            );
+        }
+    }
+
+    /// Pays `payout_rate` of the balance to the recipients of the scheme and
+    /// returns the amount paid. Nothing is paid when nobody qualifies.
+    pub(crate) fn redistribute(&self, balance: f64, agents: &mut AgentStore) -> f64 {
+        let payout = balance.max(0.0) * self.payout_rate;
+        let weights = self.recipients(agents);
+        let total_weight: f64 = weights.iter().map(|(_, w)| w).sum();
+        if payout == 0.0 || total_weight == 0.0 {
+            return 0.0;
+        }
+        for (index, weight) in weights {
+            agents[index].wealth += payout * weight / total_weight;
+        }
+        payout
+    }
+
+    /// Indices of the agents that receive transfers, with their relative shares.
+    fn recipients(&self, agents: &AgentStore) -> Vec<(usize, f64)> {
+        let adults = || {
+            agents
+                .iter()
+                .enumerate()
+                .filter(|(_, a)| a.alive && a.is_adult())
+        };
+        match self.redistribution {
+            Redistribution::BasicIncome => adults().map(|(i, _)| (i, 1.0)).collect(),
+            Redistribution::MeansTested { bottom_share } => {
+                let mut poorest: Vec<usize> = adults().map(|(i, _)| i).collect();
+                let count = (poorest.len() as f64 * bottom_share).ceil() as usize;
+                if count < poorest.len() {
+                    poorest.select_nth_unstable_by(count, |&a, &b| {
+                        agents[a].wealth.total_cmp(&agents[b].wealth)
+                    });
+                    poorest.truncate(count);
+                }
+                poorest.into_iter().map(|i| (i, 1.0)).collect()
+            }
+            Redistribution::PerChild => adults()
+                .map(|(i, a)| (i, Self::minor_children(a, agents) as f64))
+                .filter(|&(_, children)| children > 0.0)
+                .collect(),
+        }
+    }
+
+    fn minor_children(agent: &Agent, agents: &AgentStore) -> usize {
+        agent
+            .children
+            .iter()
+            .filter_map(|&child| agents.get(child))
+            .filter(|child| child.alive && !child.is_adult())
+            .count()
+    }
+}
+
+impl Budget {
+    /// Starts the flows of a new iteration; the balance carries over.
+    pub(crate) fn start_iteration(&mut self) {
+        *self = Self {
+            balance: self.balance,
+            ..Self::default()
+        };
+    }
+
+    /// All tax collected in the current iteration.
+    pub fn revenue(&self) -> f64 {
+        self.transaction_tax + self.income_tax + self.estate_tax + self.inheritance_tax
+    }
+
+    /// Estate tax as a share of the taxed estates, 0 without estates.
+    pub fn estate_tax_rate(&self) -> f64 {
+        effective_rate(self.estate_tax, self.estates)
+    }
+
+    /// Inheritance tax as a share of the taxed inheritances, 0 without any.
+    pub fn inheritance_tax_rate(&self) -> f64 {
+        effective_rate(self.inheritance_tax, self.inheritances)
+    }
+}
+
+fn effective_rate(tax: f64, base: f64) -> f64 {
+    if base > 0.0 {
+        tax / base
+    } else {
+        0.0
+    }
+}
+
+fn full_payout() -> f64 {
+    1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::AgentId;
+
+    /// Adults with the given wealth, in id order.
+    fn adults(wealth: &[f64]) -> AgentStore {
+        AgentStore::new(
+            wealth
+                .iter()
+                .enumerate()
+                .map(|(i, &w)| {
+                    let mut agent = Agent::newborn(AgentId(i), 0.0, 0.0, 80.0, 0.1);
+                    agent.age = 30 * 12;
+                    agent.wealth = w;
+                    agent
+                })
+                .collect(),
+        )
+    }
+
+    fn means_tested(bottom_share: f64) -> Government {
+        Government {
+            redistribution: Redistribution::MeansTested { bottom_share },
+            payout_rate: 1.0,
+        }
+    }
+
+    #[test]
+    fn means_tested_payouts_reach_exactly_the_poorest_share() {
+        for (n, bottom_share) in [(7, 0.3), (10, 0.3), (10, 0.05), (5, 1.0), (100, 0.25)] {
+            // Wealth in a shuffled order, so that ids do not follow wealth
+            let wealth: Vec<f64> = (0..n).map(|i| ((i * 37) % n) as f64 * 10.0).collect();
+            let mut agents = adults(&wealth);
+            let paid = means_tested(bottom_share).redistribute(100.0, &mut agents);
+            assert_eq!(paid, 100.0);
+
+            let expected = (n as f64 * bottom_share).ceil() as usize;
+            let recipients: Vec<usize> =
+                (0..n).filter(|&i| agents[i].wealth != wealth[i]).collect();
+            assert_eq!(
+                recipients.len(),
+                expected,
+                "n = {}, share = {}",
+                n,
+                bottom_share
+            );
+            let threshold = {
+                let mut sorted = wealth.clone();
+                sorted.sort_by(f64::total_cmp);
+                sorted[expected - 1]
+            };
+            for i in recipients {
+                assert!(wealth[i] <= threshold);
+                assert!((agents[i].wealth - wealth[i] - 100.0 / expected as f64).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn minors_and_the_dead_do_not_qualify() {
+        let mut agents = adults(&[0.0, 0.0, 50.0, 60.0]);
+        agents[0].age = 10 * 12;
+        agents[1].alive = false;
+        means_tested(0.5).redistribute(10.0, &mut agents);
+        assert_eq!(agents[0].wealth, 0.0);
+        assert_eq!(agents[1].wealth, 0.0);
+        assert_eq!(agents[2].wealth, 60.0);
+        assert_eq!(agents[3].wealth, 60.0);
+    }
+
+    #[test]
+    fn basic_income_pays_the_payout_rate_equally() {
+        let mut agents = adults(&[1.0, 2.0, 3.0, 4.0]);
+        let government = Government {
+            redistribution: Redistribution::BasicIncome,
+            payout_rate: 0.5,
+        };
+        assert_eq!(government.redistribute(40.0, &mut agents), 20.0);
+        let wealth: Vec<f64> = agents.iter().map(|a| a.wealth).collect();
+        assert_eq!(wealth, [6.0, 7.0, 8.0, 9.0]);
+    }
+
+    #[test]
+    fn per_child_payouts_follow_the_number_of_minor_children() {
+        let mut agents = adults(&[0.0, 0.0, 0.0, 0.0, 0.0]);
+        for child in [3, 4] {
+            agents[child].age = 5 * 12;
+        }
+        agents[0].children = vec![AgentId(3), AgentId(4)];
+        agents[1].children = vec![AgentId(4)];
+        let government = Government {
+            redistribution: Redistribution::PerChild,
+            payout_rate: 1.0,
+        };
+        government.redistribute(30.0, &mut agents);
+        let wealth: Vec<f64> = agents.iter().map(|a| a.wealth).collect();
+        assert_eq!(wealth, [20.0, 10.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn nothing_is_paid_without_recipients_or_balance() {
+        let mut agents = adults(&[1.0]);
+        agents[0].alive = false;
+        assert_eq!(means_tested(0.5).redistribute(10.0, &mut agents), 0.0);
+        let mut agents = adults(&[1.0]);
+        assert_eq!(means_tested(0.5).redistribute(-5.0, &mut agents), 0.0);
+        assert_eq!(agents[0].wealth, 1.0);
+    }
+}
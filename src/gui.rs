@@ -1,45 +1,54 @@
 use eframe::egui;
+use std::path::PathBuf;
 
-use crate::{environment::Environment, metrics::Metrics};
+use wealth_distribution_simulation::Environment;
 
 pub struct SimApp {
     pub env: Environment,
-    pub max_iter: Option<usize>,
-    pub metrics: Metrics,
-    pub logging_enabled: bool,
+    /// Metrics file the convergence summary is written next to, if any.
+    pub metrics_filepath: Option<PathBuf>,
+    pub stopped: bool,
 }
 
 impl SimApp {
-    pub fn new(
-        env: Environment,
-        filepath: Option<&str>,
-        logging_enabled: bool,
-    ) -> Self {
-        let metrics = Metrics::new(filepath.unwrap_or("visualisation/metrics.csv"));
-        let num_iterations = env.config.num_iterations;
+    pub fn new(env: Environment, metrics_filepath: Option<PathBuf>) -> Self {
         Self {
             env,
-            max_iter: Some(num_iterations),
-            metrics,
-            logging_enabled,
+            metrics_filepath,
+            stopped: false,
+        }
+    }
+
+    /// Stops stepping once `num_iterations` is reached or the run converges,
+    /// finishing the run as a headless one does.
+    fn stop_if_finished(&mut self) {
+        if self.stopped || !self.env.finished() {
+            return;
+        }
+        self.stopped = true;
+        if let Err(e) = self.env.finish() {
+            eprintln!("Failed to write metrics: {}", e);
+        }
+        if let Some(path) = &self.metrics_filepath {
+            if let Err(e) = crate::save_convergence_summary(&self.env, path) {
+                eprintln!("Failed to write convergence summary: {}", e);
+            }
         }
     }
 }
 
 impl eframe::App for SimApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if self.max_iter.is_some() && self.env.iteration >= self.max_iter.unwrap() {
+        self.stop_if_finished();
+        if self.stopped {
             ctx.request_repaint();
             return;
         }
-        self.env.step();
-        if self.logging_enabled {
-            self.metrics.log(
-                self.env.iteration,
-                &self.env.agents,
-                self.env.iteration_total_transaction_amount,
-                self.env.iteration_total_transaction_count,
-            );
+        if let Err(e) = self.env.step() {
+            eprintln!("Stopping the simulation: {}", e);
+            self.stopped = true;
+            ctx.request_repaint();
+            return;
         }
 
         egui::CentralPanel::default().show(ctx, |ui| {
@@ -92,7 +101,7 @@ impl eframe::App for SimApp {
                     ((agent.wealth - min_wealth) / (max_wealth - min_wealth) * 255.0)
                         .clamp(0.0, 255.0) as u8
                 };
-                let color = egui::Color32::from_rgb(wealth as u8, 100, 255 - wealth as u8);
+                let color = egui::Color32::from_rgb(wealth, 100, 255 - wealth);
 
                 painter.circle_filled(screen_pos, 3.0, color);
             }
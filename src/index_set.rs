@@ -0,0 +1,93 @@
+/// Set of indices below a fixed bound, kept in a Fenwick tree so that updates and
+/// finding the k-th smallest member take O(log n).
+pub(crate) struct IndexSet {
+    members: Vec<bool>,
+    tree: Vec<usize>, // 1-based Fenwick tree of member counts
+    len: usize,
+}
+
+impl IndexSet {
+    /// Set of the indices `i` for which `members[i]` is true.
+    pub(crate) fn new(members: Vec<bool>) -> Self {
+        let n = members.len();
+        let mut tree = vec![0; n + 1];
+        for (i, &member) in members.iter().enumerate() {
+            tree[i + 1] += member as usize;
+            let parent = (i + 1) + ((i + 1) & (i + 1).wrapping_neg());
+            if parent <= n {
+                tree[parent] += tree[i + 1];
+            }
+        }
+        let len = members.iter().filter(|&&m| m).count();
+        Self { members, tree, len }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Adds or removes index `i`.
+    pub(crate) fn set(&mut self, i: usize, member: bool) {
+        if self.members[i] == member {
+            return;
+        }
+        self.members[i] = member;
+        let mut position = i + 1;
+        while position < self.tree.len() {
+            if member {
+                self.tree[position] += 1;
+            } else {
+                self.tree[position] -= 1;
+            }
+            position += position & position.wrapping_neg();
+        }
+        if member {
+            self.len += 1;
+        } else {
+            self.len -= 1;
+        }
+    }
+
+    /// The `k`-th smallest member, counting from 0. `k` must be below `len()`.
+    pub(crate) fn nth(&self, mut k: usize) -> usize {
+        let mut position = 0;
+        let mut step = (self.tree.len() - 1).next_power_of_two();
+        while step > 0 {
+            let next = position + step;
+            if next < self.tree.len() && self.tree[next] <= k {
+                position = next;
+                k -= self.tree[next];
+            }
+            step /= 2;
+        }
+        position // the member's 1-based tree position minus one, i.e. its index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn nth_set_and_len_match_a_plain_vec_after_random_updates() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        for n in [1, 2, 7, 64, 100, 257] {
+            let mut model: Vec<bool> = (0..n).map(|_| rng.gen_bool(0.5)).collect();
+            let mut set = IndexSet::new(model.clone());
+            for _ in 0..500 {
+                let i = rng.gen_range(0..n);
+                let member = rng.gen_bool(0.5);
+                model[i] = member;
+                set.set(i, member);
+
+                let members: Vec<usize> = (0..n).filter(|&i| model[i]).collect();
+                assert_eq!(set.len(), members.len());
+                for (k, &member) in members.iter().enumerate() {
+                    assert_eq!(set.nth(k), member, "n {}, k {}", n, k);
+                }
+            }
+        }
+    }
+}
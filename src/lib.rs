@@ -0,0 +1,38 @@
+//! Agent-based simulation of wealth and education distribution.
+//!
+//! Build an [`Environment`] from an [`EnvironmentConfig`], advance it with
+//! [`Environment::step`] and read the population through [`Environment::agents`]
+//! or [`Environment::stats`]. Per-step statistics can be streamed to any
+//! [`MetricsSink`], such as the CSV writer [`Metrics`].
+
+pub mod agent;
+pub mod agent_store;
+pub mod batch;
+pub mod branch;
+pub mod calibration;
+mod checkpoint;
+pub mod config_error;
+mod config_file;
+pub mod config_migration;
+pub mod convergence;
+pub mod environment;
+pub mod environment_config;
+pub mod government;
+mod index_set;
+pub mod metrics;
+pub mod population;
+pub mod replicates;
+pub mod schedule;
+pub mod sensitivity;
+mod spatial;
+pub mod sweep;
+pub mod taxation;
+
+pub use agent::{Agent, AgentId};
+pub use agent_store::AgentStore;
+pub use config_error::{ConfigError, ValidationIssue};
+pub use config_file::ConfigFormat;
+pub use environment::Environment;
+pub use environment_config::EnvironmentConfig;
+pub use metrics::{Metrics, MetricsSink, Snapshot, Stats};
+pub use sweep::{Sweep, SweepParameter, SweepRun};
@@ -1,77 +1,630 @@
-mod agent;
-mod environment;
-mod environment_config;
+mod cli;
 mod gui;
-mod metrics;
 
-use std::env;
-use std::path::Path;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-use crate::environment::Environment;
-use crate::environment_config::{
-    AgeAndDeath, Education, EnvironmentConfig, IncomeAndConsumption, Transaction,
+use clap::Parser;
+use rayon::prelude::*;
+use wealth_distribution_simulation::branch::{self, Branch};
+use wealth_distribution_simulation::calibration::CalibrationSpec;
+use wealth_distribution_simulation::population;
+use wealth_distribution_simulation::replicates::{self, Trajectory};
+use wealth_distribution_simulation::sensitivity::SensitivitySpec;
+use wealth_distribution_simulation::{
+    ConfigError, Environment, EnvironmentConfig, Metrics, MetricsSink, Sweep, SweepParameter,
 };
+
+use crate::cli::{BranchArgs, Cli, Command, ResumeArgs, RunArgs, RunOverrides, SweepArgs};
 use crate::gui::SimApp;
 
-fn create_default_config() {
-    let age_and_death = AgeAndDeath::new(30.0, 10.0, 80.0, 90.0, 0.02);
-    let education = Education::new(4.0, 10.0, 4.0, 2.0, 0.005, 0.05, 10.0);
-    let income_and_consumption = IncomeAndConsumption::new(0.05, 2.0, 10.0, 0.2);
-    let transaction = Transaction::new(0.3, 1.0, 0.001, 0.05, 0.05);
-    let wealth = environment_config::Wealth::new(10.0, 100.0, 0.1, 0.3);
-    let config = EnvironmentConfig::new(
-        5000,
-        1000,
-        1000,
-        1000,
-        50.0,
-        15.0,
-        age_and_death,
-        education,
-        income_and_consumption,
-        transaction,
-        wealth,
+fn config_name(config_path: &Path) -> &str {
+    config_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("default")
+}
+
+fn print_warnings(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+fn load_config(
+    config_path: &Path,
+    overrides: &RunOverrides,
+) -> Result<EnvironmentConfig, ConfigError> {
+    println!("Loading config from: {}", config_path.display());
+    let (mut config, warnings) =
+        EnvironmentConfig::read_from_file_with_warnings(&config_path.to_string_lossy())?;
+    print_warnings(&warnings);
+    for assignment in &overrides.set {
+        config.apply_assignment(assignment)?;
+    }
+    if let Some(iterations) = overrides.iterations {
+        config.num_iterations = iterations;
+    }
+    if overrides.seed.is_some() {
+        config.seed = overrides.seed;
+    }
+    config.validate()?;
+    if let Some(population_path) = &config.initial_population {
+        population::read(Path::new(population_path), &config)?;
+    }
+    Ok(config)
+}
+
+/// Attributes a failure to write an output file to its path.
+fn output_error(path: &Path) -> impl FnOnce(io::Error) -> ConfigError + '_ {
+    move |source| ConfigError::Io {
+        path: path.display().to_string(),
+        source,
+    }
+}
+
+/// Records the config a run actually used next to its metrics file.
+fn save_effective_config(
+    config: &EnvironmentConfig,
+    metrics_filepath: &Path,
+) -> Result<(), ConfigError> {
+    config.save_to_file(
+        &metrics_filepath
+            .with_extension("config.json")
+            .to_string_lossy(),
+    )
+}
+
+/// Writes the convergence iteration and steady-state averages next to the
+/// metrics file of a run with convergence criteria.
+fn save_convergence_summary(env: &Environment, metrics_filepath: &Path) -> Result<(), ConfigError> {
+    match env.convergence_summary() {
+        Some(summary) => summary.save_to_file(
+            &metrics_filepath
+                .with_extension("summary.json")
+                .to_string_lossy(),
+        ),
+        None => Ok(()),
+    }
+}
+
+fn metrics_filepath(args: &RunArgs, suffix: &str) -> PathBuf {
+    args.output.clone().unwrap_or_else(|| {
+        PathBuf::from(format!(
+            "visualisation/metrics_{}{}.csv",
+            config_name(&args.config),
+            suffix
+        ))
+    })
+}
+
+/// Creates the environment of `config`, loaded from `args`, with the metrics
+/// and checkpoints `args` ask for.
+fn build_environment(
+    config: &EnvironmentConfig,
+    args: &RunArgs,
+) -> Result<Environment, ConfigError> {
+    let mut env = Environment::try_new(config)?;
+
+    if !args.no_metrics {
+        let metrics_filepath = metrics_filepath(args, "");
+        save_effective_config(env.config(), &metrics_filepath)?;
+        let metrics = Metrics::new(&metrics_filepath.to_string_lossy())
+            .map_err(output_error(&metrics_filepath))?
+            .with_interval(args.overrides.metrics_interval);
+        env.add_sink(Box::new(if args.background_metrics {
+            metrics.in_background()
+        } else {
+            metrics
+        }));
+    }
+    if let Some(every) = args.checkpoint_every {
+        let checkpoint_filepath = args
+            .checkpoint
+            .clone()
+            .unwrap_or_else(|| metrics_filepath(args, "").with_extension("checkpoint"));
+        env.checkpoint_every(&checkpoint_filepath, every);
+    }
+    Ok(env)
+}
+
+/// Limits the threads a single run updates its agents on. Results do not
+/// depend on the number of threads.
+fn set_threads(jobs: Option<usize>) -> Result<(), Box<dyn Error>> {
+    if let Some(jobs) = jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()?;
+    }
+    Ok(())
+}
+
+fn run(args: &RunArgs) -> Result<(), Box<dyn Error>> {
+    set_threads(args.jobs)?;
+    let mut config = load_config(&args.config, &args.overrides)?;
+    if config.replicates <= 1 {
+        let mut env = build_environment(&config, args)?;
+        env.run()?;
+        if !args.no_metrics {
+            save_convergence_summary(&env, &metrics_filepath(args, ""))?;
+        }
+        if let Some(agents_path) = &args.save_agents {
+            population::write_csv(agents_path, env.agents())?;
+            println!("Final population written to: {}", agents_path.display());
+        }
+        return Ok(());
+    }
+    if args.checkpoint_every.is_some() || args.save_agents.is_some() {
+        return Err(
+            "checkpoints and --save-agents are not supported for runs with replicates".into(),
+        );
+    }
+    println!("Running {} replicates", config.replicates);
+    let (base_seed, trajectories) =
+        replicates::run_replicates(&config, args.overrides.metrics_interval)?;
+    println!(
+        "Replicate seeds: {}..={}",
+        base_seed,
+        base_seed.wrapping_add(config.replicates as u64 - 1)
     );
-    config.save_to_file("config/default.json");
+    // Record the base seed so the saved config reproduces every replicate
+    config.seed = Some(base_seed);
+    if !args.no_metrics {
+        let metrics_filepath = metrics_filepath(args, "_replicates");
+        save_effective_config(&config, &metrics_filepath)?;
+        replicates::write_aggregate(&metrics_filepath.to_string_lossy(), &trajectories)?;
+        println!(
+            "Aggregated metrics written to: {}",
+            metrics_filepath.display()
+        );
+    }
+    Ok(())
 }
 
-fn main() -> eframe::Result<()> {
-    let args: Vec<String> = env::args().collect();
+fn resume(args: &ResumeArgs) -> Result<(), Box<dyn Error>> {
+    println!("Resuming from checkpoint: {}", args.checkpoint.display());
+    let mut env = Environment::load_checkpoint(&args.checkpoint)?;
+    if let Some(iterations) = args.iterations {
+        env.config.num_iterations = iterations;
+    }
+    let metrics_filepath = args
+        .output
+        .clone()
+        .unwrap_or_else(|| args.checkpoint.with_extension("csv"));
+    if !args.no_metrics {
+        let metrics = Metrics::resume(&metrics_filepath.to_string_lossy(), env.iteration())
+            .map_err(output_error(&metrics_filepath))?
+            .with_interval(args.metrics_interval);
+        env.add_sink(Box::new(if args.background_metrics {
+            metrics.in_background()
+        } else {
+            metrics
+        }));
+    }
+    if let Some(every) = args.checkpoint_every {
+        env.checkpoint_every(&args.checkpoint, every);
+    }
+    println!(
+        "Continuing at iteration {} of {}",
+        env.iteration(),
+        env.config().num_iterations
+    );
 
-    create_default_config();
+    if args.gui {
+        let metrics_filepath = (!args.no_metrics).then_some(metrics_filepath);
+        return run_gui(env, metrics_filepath).map_err(Into::into);
+    }
+    env.run()?;
+    if !args.no_metrics {
+        save_convergence_summary(&env, &metrics_filepath)?;
+    }
+    Ok(())
+}
 
-    let config_path = if args.len() > 1 {
-        println!("Loading config from: {}", args[1]);
-        args[1].as_str()
-    } else {
-        println!("No config file provided, using default.");
-        "config/default.json"
-    };
-    let config_filename = Path::new(config_path)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("default");
+fn branch(args: &BranchArgs) -> Result<(), Box<dyn Error>> {
+    let mut branches = vec![Branch::baseline()];
+    for spec in &args.branches {
+        let branch = Branch::parse(spec)?;
+        if branches.iter().any(|b| b.name == branch.name) {
+            return Err(format!("branch `{}` is given twice", branch.name).into());
+        }
+        branches.push(branch);
+    }
+
+    fs::create_dir_all(&args.output_dir)?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.unwrap_or(0))
+        .build()?;
+    println!(
+        "Running {} branches from {} on {} threads",
+        branches.len(),
+        args.checkpoint.display(),
+        pool.current_num_threads()
+    );
 
-    let config = EnvironmentConfig::load_from_file(config_path);
-    let mut env = Environment::new(&config);
+    // Every branch restores the same RNG state, so they draw common random numbers.
+    // Every branch runs even if others fail, as the points of a sweep do
+    let trajectories: Vec<Result<Trajectory, ConfigError>> = pool.install(|| {
+        branches
+            .par_iter()
+            .map(|b| -> Result<_, ConfigError> {
+                let mut env = Environment::load_checkpoint(&args.checkpoint)?;
+                b.apply(&mut env)?;
+                if let Some(iterations) = args.iterations {
+                    env.config.num_iterations = iterations;
+                }
+                let metrics_filepath = args
+                    .output_dir
+                    .join(format!("metrics_branch_{}.csv", b.name));
+                save_effective_config(env.config(), &metrics_filepath)?;
+                // Fed here instead of attached as a sink, so that the statistics of a
+                // logged iteration are computed once for the file and the differences
+                let mut metrics = Metrics::new(&metrics_filepath.to_string_lossy())
+                    .map_err(output_error(&metrics_filepath))?
+                    .with_interval(args.metrics_interval);
+                let mut trajectory = Vec::new();
+                while !env.finished() {
+                    env.step()?;
+                    if metrics.wants(env.iteration()) {
+                        if let Some(stats) = env.stats() {
+                            metrics.record(&stats);
+                            trajectory.push(stats);
+                        }
+                    }
+                }
+                env.finish().map_err(output_error(&metrics_filepath))?;
+                metrics.finish().map_err(output_error(&metrics_filepath))?;
+                save_convergence_summary(&env, &metrics_filepath)?;
+                println!("{}: done", b.name);
+                Ok(trajectory)
+            })
+            .collect()
+    });
 
+    // Only completed branches are compared, and only against a completed baseline
+    let mut names = Vec::new();
+    let mut completed = Vec::new();
+    let mut failed = 0;
+    for (b, trajectory) in branches.iter().zip(trajectories) {
+        match trajectory {
+            Ok(trajectory) => {
+                names.push(b.name.clone());
+                completed.push(trajectory);
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("{}: failed: {}", b.name, e);
+            }
+        }
+    }
+    if names.first().is_some_and(|name| name == branch::BASELINE) {
+        let differences_filepath = args.output_dir.join("branch_differences.csv");
+        branch::write_differences(&differences_filepath.to_string_lossy(), &names, &completed)?;
+        println!("Differences written to: {}", differences_filepath.display());
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} branches failed", failed, branches.len()).into());
+    }
+    Ok(())
+}
+
+fn run_gui(env: Environment, metrics_filepath: Option<PathBuf>) -> eframe::Result<()> {
     let native_options: eframe::NativeOptions = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([800.0, 800.0]),
         ..Default::default()
     };
 
-    let metrics_filepath = format!("visualisation/metrics_{}.csv", config_filename);
-
-    let should_visualise = env::var("VISUALISE").unwrap_or_else(|_| "true".to_string());
-    if should_visualise.to_lowercase() == "false" {
-        println!("Visualisation disabled. Running simulation without GUI.");
-        env.run_simulation(Some(&metrics_filepath), true);
-        return Ok(());
-    }
-
     eframe::run_native(
         "Wealth Simulation",
         native_options,
-        Box::new(move |_cc| Box::new(SimApp::new(env, Some(&metrics_filepath), true))),
+        Box::new(move |_cc| Box::new(SimApp::new(env, metrics_filepath))),
     )
 }
+
+fn init_config(path: &Path, force: bool) -> Result<(), Box<dyn Error>> {
+    if path.exists() && !force {
+        return Err(format!(
+            "{} already exists, use --force to overwrite it",
+            path.display()
+        )
+        .into());
+    }
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    EnvironmentConfig::default().save_to_file(&path.to_string_lossy())?;
+    println!("Default config written to: {}", path.display());
+    Ok(())
+}
+
+fn validate(configs: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+    let mut failed = 0;
+    for config_path in configs {
+        let loaded =
+            EnvironmentConfig::read_from_file_with_warnings(&config_path.to_string_lossy())
+                .and_then(|(config, warnings)| {
+                    print_warnings(&warnings);
+                    config.validate()
+                });
+        match loaded {
+            Ok(()) => println!("{}: ok", config_path.display()),
+            Err(e @ ConfigError::Invalid(_)) => {
+                failed += 1;
+                println!("{}: {}", config_path.display(), e);
+            }
+            Err(e) => {
+                failed += 1;
+                println!("{}", e);
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} configs are invalid", failed, configs.len()).into());
+    }
+    Ok(())
+}
+
+fn migrate(configs: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+    for config_path in configs {
+        match EnvironmentConfig::migrate_file(&config_path.to_string_lossy())? {
+            Some(warnings) => println!(
+                "{}: migrated ({} deprecated fields rewritten)",
+                config_path.display(),
+                warnings.len()
+            ),
+            None => println!("{}: already in the current schema", config_path.display()),
+        }
+    }
+    Ok(())
+}
+
+fn schema(output: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let schema = serde_json::to_string_pretty(&EnvironmentConfig::json_schema())?;
+    match output {
+        Some(path) => fs::write(path, schema + "\n")?,
+        None => println!("{}", schema),
+    }
+    Ok(())
+}
+
+fn sweep(args: &SweepArgs) -> Result<(), Box<dyn Error>> {
+    let parameters = args
+        .vary
+        .iter()
+        .map(|spec| SweepParameter::parse(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    let sweep = Sweep::new(parameters, args.zip);
+
+    let mut runs = Vec::new();
+    for config_path in &args.configs {
+        let base = load_config(config_path, &args.overrides)?;
+        for run in sweep.runs(&base)? {
+            let run_id = if sweep.parameters.is_empty() {
+                config_name(config_path).to_string()
+            } else {
+                format!("{}_{:03}", config_name(config_path), run.index)
+            };
+            runs.push((run_id, config_path.clone(), run));
+        }
+    }
+
+    fs::create_dir_all(&args.output_dir)?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.unwrap_or(0))
+        .build()?;
+    println!(
+        "Running {} simulations on {} threads",
+        runs.len(),
+        pool.current_num_threads()
+    );
+
+    // Every point runs even if others fail, so one bad point does not lose the rest
+    let seeds: Vec<Result<u64, ConfigError>> = pool.install(|| {
+        runs.par_iter()
+            .map(|(run_id, _, run)| -> Result<u64, ConfigError> {
+                let metrics_filepath = args.output_dir.join(format!("metrics_{}.csv", run_id));
+                let mut env = Environment::try_new(&run.config)?;
+                save_effective_config(env.config(), &metrics_filepath)?;
+                env.add_sink(Box::new(
+                    Metrics::new(&metrics_filepath.to_string_lossy())
+                        .map_err(output_error(&metrics_filepath))?
+                        .with_interval(args.overrides.metrics_interval),
+                ));
+                while !env.finished() {
+                    env.step()?;
+                }
+                env.finish().map_err(output_error(&metrics_filepath))?;
+                save_convergence_summary(&env, &metrics_filepath)?;
+                println!("{}: done", run_id);
+                Ok(env.config().seed.unwrap_or_default())
+            })
+            .collect()
+    });
+
+    // Only runs that completed are indexed
+    let index_filepath = args.output_dir.join("sweep_index.csv");
+    let mut header = vec![
+        "run_id".to_string(),
+        "config".to_string(),
+        "seed".to_string(),
+    ];
+    header.extend(sweep.parameters.iter().map(|p| csv_field(&p.path)));
+    header.push("metrics_file".to_string());
+    let mut index = header.join(",") + "\n";
+    let mut failed = 0;
+    for ((run_id, config_path, run), seed) in runs.iter().zip(seeds) {
+        let seed = match seed {
+            Ok(seed) => seed,
+            Err(e) => {
+                failed += 1;
+                eprintln!("{}: failed: {}", run_id, e);
+                continue;
+            }
+        };
+        let mut row = vec![
+            csv_field(run_id),
+            csv_field(&config_path.display().to_string()),
+            seed.to_string(),
+        ];
+        row.extend(run.values.iter().map(|value| match value {
+            serde_json::Value::String(s) => csv_field(s),
+            other => csv_field(&other.to_string()),
+        }));
+        row.push(csv_field(&format!("metrics_{}.csv", run_id)));
+        index.push_str(&(row.join(",") + "\n"));
+    }
+    fs::write(&index_filepath, index)?;
+    println!("Run index written to: {}", index_filepath.display());
+    if failed > 0 {
+        return Err(format!("{} of {} runs failed", failed, runs.len()).into());
+    }
+    Ok(())
+}
+
+/// Quotes a CSV field if it contains a comma, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn sensitivity(
+    spec_path: &Path,
+    config_path: &Path,
+    output_dir: &Path,
+    jobs: Option<usize>,
+    overrides: &RunOverrides,
+) -> Result<(), Box<dyn Error>> {
+    let spec = SensitivitySpec::load_from_file(&spec_path.to_string_lossy())?;
+    let base = load_config(config_path, overrides)?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.unwrap_or(0))
+        .build()?;
+    println!(
+        "Running {} simulations on {} threads",
+        spec.num_runs(),
+        pool.current_num_threads()
+    );
+    let result = pool.install(|| spec.run(&base))?;
+
+    fs::create_dir_all(output_dir)?;
+    let samples_filepath = output_dir.join("sensitivity_samples.csv");
+    let indices_filepath = output_dir.join("sensitivity_indices.csv");
+    result.write_samples(&samples_filepath.to_string_lossy())?;
+    result.write_indices(&indices_filepath.to_string_lossy())?;
+    println!(
+        "Samples written to: {}\nIndices written to: {}",
+        samples_filepath.display(),
+        indices_filepath.display()
+    );
+    Ok(())
+}
+
+fn calibrate(
+    spec_path: &Path,
+    config_path: &Path,
+    output_dir: &Path,
+    jobs: Option<usize>,
+    overrides: &RunOverrides,
+) -> Result<(), Box<dyn Error>> {
+    let spec = CalibrationSpec::load_from_file(&spec_path.to_string_lossy())?;
+    let base = load_config(config_path, overrides)?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.unwrap_or(0))
+        .build()?;
+    println!("Calibrating on {} threads", pool.current_num_threads());
+    let result = pool.install(|| spec.run(&base))?;
+
+    let best = &result.evaluations[result.best];
+    println!(
+        "Best fit after {} simulations, error {:.6}:",
+        result.evaluations.len(),
+        best.error
+    );
+    for ((parameter, value), (mean, std)) in spec
+        .parameters
+        .iter()
+        .zip(&best.values)
+        .zip(result.posterior())
+    {
+        if result.accepted.len() > 1 {
+            println!(
+                "  {} = {} (posterior {:.6} ± {:.6})",
+                parameter.path, value, mean, std
+            );
+        } else {
+            println!("  {} = {}", parameter.path, value);
+        }
+    }
+    for (target, output) in spec.targets.iter().zip(&best.outputs) {
+        println!(
+            "  {}: {:.5} (target {})",
+            target.output, output, target.value
+        );
+    }
+
+    fs::create_dir_all(output_dir)?;
+    let evaluations_filepath = output_dir.join("calibration_evaluations.csv");
+    let config_filepath = output_dir.join("calibration_best.config.json");
+    result.write_evaluations(&evaluations_filepath.to_string_lossy())?;
+    result
+        .config
+        .save_to_file(&config_filepath.to_string_lossy())?;
+    println!(
+        "Evaluations written to: {}\nBest config written to: {}",
+        evaluations_filepath.display(),
+        config_filepath.display()
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Run(args) => run(&args),
+        Command::Gui(args) => set_threads(args.jobs)
+            .and_then(|()| {
+                let config = load_config(&args.config, &args.overrides)?;
+                build_environment(&config, &args).map_err(Into::into)
+            })
+            .and_then(|env| {
+                let metrics_filepath = (!args.no_metrics).then(|| metrics_filepath(&args, ""));
+                run_gui(env, metrics_filepath).map_err(Into::into)
+            }),
+        Command::Resume(args) => resume(&args),
+        Command::Branch(args) => branch(&args),
+        Command::InitConfig { path, force } => init_config(&path, force),
+        Command::Validate { configs } => validate(&configs),
+        Command::Migrate { configs } => migrate(&configs),
+        Command::Schema { output } => schema(output.as_deref()),
+        Command::Sweep(args) => sweep(&args),
+        Command::Sensitivity {
+            spec,
+            config,
+            output_dir,
+            jobs,
+            overrides,
+        } => sensitivity(&spec, &config, &output_dir, jobs, &overrides),
+        Command::Calibrate {
+            spec,
+            config,
+            output_dir,
+            jobs,
+            overrides,
+        } => calibrate(&spec, &config, &output_dir, jobs, &overrides),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,103 +1,327 @@
 use crate::agent::Agent;
-use std::fs::OpenOptions;
-use std::io::Write;
+use crate::government::Budget;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::{self, JoinHandle};
 
-pub struct Metrics {
-    file_path: String,
+/// Aggregate wealth and education statistics of the living population at one iteration.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Stats {
+    pub iteration: usize,
+    pub total_transactions_amount: f64,
+    pub transactions_count: u32,
+    pub gini: f64,
+    pub min: f64,
+    pub p10: f64,
+    pub p25: f64,
+    pub p50: f64,
+    pub p75: f64,
+    pub p90: f64,
+    pub max: f64,
+    pub total_wealth: f64,
+    pub adult_agents: usize,
+    pub edu_mean: f64,
+    pub edu_min: f64,
+    pub edu_p10: f64,
+    pub edu_p25: f64,
+    pub edu_p50: f64,
+    pub edu_p75: f64,
+    pub edu_p90: f64,
+    pub edu_max: f64,
+    pub top10_share: f64, // share of total wealth held by the richest 10%
+    pub median_mean_ratio: f64,
+    pub tax_revenue: f64,
+    pub transaction_tax_revenue: f64,
+    pub income_tax_revenue: f64,
+    pub estate_tax_revenue: f64,
+    pub inheritance_tax_revenue: f64,
+    pub estate_tax_rate: f64,      // effective rate on the taxed estates
+    pub inheritance_tax_rate: f64, // effective rate on the taxed inheritances
+    pub government_spending: f64,
+    pub government_balance: f64,
 }
 
-impl Metrics {
-    pub fn new(file_path: &str) -> Self {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(file_path)
-            .expect("Failed to create metrics file");
-
-        writeln!(
-            file,
-            "iteration,total_transactions_amount,transactions_count,gini,min,p10,p25,p50,p75,p90,max,total_wealth,adult_agents,edu_mean,edu_min,edu_p10,edu_p25,edu_p50,edu_p75,edu_p90,edu_max"
-        )
-        .unwrap();
+/// Values of the living agents after a step, from which [`Stats`] are computed.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub iteration: usize,
+    pub wealths: Vec<f64>,
+    pub educations: Vec<f64>,
+    pub adult_agents: usize,
+    pub total_transactions_amount: f64,
+    pub transactions_count: u32,
+    pub budget: Budget,
+}
 
+impl Snapshot {
+    pub fn take(
+        iteration: usize,
+        agents: &[Agent],
+        iteration_total_transaction_amount: f64,
+        iteration_total_transaction_count: u32,
+        budget: Budget,
+    ) -> Self {
+        let living = || agents.iter().filter(|a| a.alive);
         Self {
-            file_path: file_path.to_string(),
+            iteration,
+            wealths: living().map(|a| a.wealth).collect(),
+            educations: living().map(|a| a.education).collect(),
+            adult_agents: living().filter(|a| a.is_adult()).count(),
+            total_transactions_amount: iteration_total_transaction_amount,
+            transactions_count: iteration_total_transaction_count,
+            budget,
         }
     }
+}
 
-    pub fn log(
-        &self,
+/// Receives the statistics computed after every simulation step.
+pub trait MetricsSink {
+    fn record(&mut self, stats: &Stats);
+
+    /// Whether the sink records `iteration`. Statistics are only computed for
+    /// the iterations that some sink wants.
+    fn wants(&self, _iteration: usize) -> bool {
+        true
+    }
+
+    /// Whether the sink computes the statistics itself from a [`Snapshot`],
+    /// passed to [`MetricsSink::record_snapshot`] instead of calling `record`.
+    fn takes_snapshots(&self) -> bool {
+        false
+    }
+
+    fn record_snapshot(&mut self, snapshot: Snapshot) {
+        if let Some(stats) = Stats::from_snapshot(snapshot) {
+            self.record(&stats);
+        }
+    }
+
+    /// Makes everything recorded so far durable, e.g. before a checkpoint, and
+    /// reports the first error the sink has run into.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// CSV writer of the statistics, writing every `log_every`-th iteration through
+/// a buffer that is kept open for the whole run. Writing stops at the first
+/// error, which [`MetricsSink::flush`] and [`Metrics::finish`] report.
+pub struct Metrics {
+    writer: Writer,
+    log_every: usize,
+    error: Option<io::Error>,
+}
+
+enum Writer {
+    File(BufWriter<File>),
+    Background {
+        messages: Option<SyncSender<Message>>, // `None` once the thread is told to stop
+        thread: Option<JoinHandle<io::Result<()>>>,
+    },
+}
+
+enum Message {
+    Stats(Stats),
+    Snapshot(Snapshot),
+    Flush(SyncSender<io::Result<()>>), // answered once everything before it is written
+}
+
+/// Snapshots a background writer can fall behind by before steps wait for it.
+const BACKGROUND_QUEUE: usize = 8;
+
+impl Stats {
+    pub const COLUMN_COUNT: usize = 31;
+
+    /// Names of every statistic except the iteration, as in the metrics CSV header.
+    pub const COLUMN_NAMES: [&'static str; Self::COLUMN_COUNT] = [
+        "total_transactions_amount",
+        "transactions_count",
+        "gini",
+        "min",
+        "p10",
+        "p25",
+        "p50",
+        "p75",
+        "p90",
+        "max",
+        "total_wealth",
+        "adult_agents",
+        "edu_mean",
+        "edu_min",
+        "edu_p10",
+        "edu_p25",
+        "edu_p50",
+        "edu_p75",
+        "edu_p90",
+        "edu_max",
+        "top10_share",
+        "median_mean_ratio",
+        "tax_revenue",
+        "transaction_tax_revenue",
+        "income_tax_revenue",
+        "estate_tax_revenue",
+        "inheritance_tax_revenue",
+        "estate_tax_rate",
+        "inheritance_tax_rate",
+        "government_spending",
+        "government_balance",
+    ];
+
+    /// Decimal places each column is written with in the metrics CSV, in the
+    /// order of [`Stats::COLUMN_NAMES`]. Counts have none.
+    const COLUMN_DECIMALS: [usize; Self::COLUMN_COUNT] = [
+        2, 0, 5, 2, 2, 2, 2, 2, 2, 2, 2, 0, 2, 2, 2, 2, 2, 2, 2, 2, 5, 5, 2, 2, 2, 2, 2, 5, 5, 2, 2,
+    ];
+
+    /// Values in the order of [`Stats::COLUMN_NAMES`].
+    pub fn values(&self) -> [f64; Self::COLUMN_COUNT] {
+        [
+            self.total_transactions_amount,
+            self.transactions_count as f64,
+            self.gini,
+            self.min,
+            self.p10,
+            self.p25,
+            self.p50,
+            self.p75,
+            self.p90,
+            self.max,
+            self.total_wealth,
+            self.adult_agents as f64,
+            self.edu_mean,
+            self.edu_min,
+            self.edu_p10,
+            self.edu_p25,
+            self.edu_p50,
+            self.edu_p75,
+            self.edu_p90,
+            self.edu_max,
+            self.top10_share,
+            self.median_mean_ratio,
+            self.tax_revenue,
+            self.transaction_tax_revenue,
+            self.income_tax_revenue,
+            self.estate_tax_revenue,
+            self.inheritance_tax_revenue,
+            self.estate_tax_rate,
+            self.inheritance_tax_rate,
+            self.government_spending,
+            self.government_balance,
+        ]
+    }
+
+    /// Every statistic except the iteration, paired with its column name.
+    pub fn columns(&self) -> [(&'static str, f64); Self::COLUMN_COUNT] {
+        let values = self.values();
+        std::array::from_fn(|i| (Self::COLUMN_NAMES[i], values[i]))
+    }
+
+    /// Value of the column called `name`, if there is one.
+    pub fn column(&self, name: &str) -> Option<f64> {
+        Self::COLUMN_NAMES
+            .iter()
+            .position(|&column| column == name)
+            .map(|i| self.values()[i])
+    }
+
+    /// Returns `None` when there are no living agents to describe.
+    pub fn compute(
         iteration: usize,
         agents: &[Agent],
         iteration_total_transaction_amount: f64,
         iteration_total_transaction_count: u32,
-    ) {
-        let mut wealths: Vec<f64> = agents
-            .iter()
-            .filter(|a| a.alive)
-            .map(|a| a.wealth)
-            .collect();
-        let mut educations: Vec<f64> = agents
-            .iter()
-            .filter(|a| a.alive)
-            .map(|a| a.education)
-            .collect();
+        budget: Budget,
+    ) -> Option<Self> {
+        Self::from_snapshot(Snapshot::take(
+            iteration,
+            agents,
+            iteration_total_transaction_amount,
+            iteration_total_transaction_count,
+            budget,
+        ))
+    }
+
+    /// Returns `None` when the snapshot has no living agents.
+    pub fn from_snapshot(snapshot: Snapshot) -> Option<Self> {
+        let Snapshot {
+            iteration,
+            mut wealths,
+            mut educations,
+            adult_agents,
+            total_transactions_amount,
+            transactions_count,
+            budget,
+        } = snapshot;
 
         if wealths.is_empty() {
-            return;
+            return None;
         }
 
-        wealths.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        educations.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        // NaN sorts last instead of panicking
+        wealths.sort_unstable_by(f64::total_cmp);
+        educations.sort_unstable_by(f64::total_cmp);
 
-        let n = wealths.len();
         let gini = Self::gini(&wealths);
         let total_wealth: f64 = wealths.iter().sum();
+        let top10_count = (wealths.len() as f64 * 0.1).ceil() as usize;
+        let top10_wealth: f64 = wealths.iter().rev().take(top10_count).sum();
+        let top10_share = if total_wealth == 0.0 {
+            0.0
+        } else {
+            top10_wealth / total_wealth
+        };
 
         let percentile = |v: &Vec<f64>, p: f64| -> f64 {
             let idx = (p * v.len() as f64).floor() as usize;
             v.get(idx.min(v.len() - 1)).cloned().unwrap_or(0.0)
         };
 
-        let min = *wealths.first().unwrap_or(&0.0);
-        let max = *wealths.last().unwrap_or(&0.0);
-        let p10 = percentile(&wealths, 0.10);
-        let p25 = percentile(&wealths, 0.25);
         let p50 = percentile(&wealths, 0.50);
-        let p75 = percentile(&wealths, 0.75);
-        let p90 = percentile(&wealths, 0.90);
+        let mean = total_wealth / wealths.len() as f64;
+        let median_mean_ratio = if mean == 0.0 { 0.0 } else { p50 / mean };
 
-        let edu_min = *educations.first().unwrap_or(&0.0);
-        let edu_max = *educations.last().unwrap_or(&0.0);
         let edu_mean = if !educations.is_empty() {
             educations.iter().sum::<f64>() / educations.len() as f64
         } else {
             0.0
         };
-        let edu_p10 = percentile(&educations, 0.10);
-        let edu_p25 = percentile(&educations, 0.25);
-        let edu_p50 = percentile(&educations, 0.50);
-        let edu_p75 = percentile(&educations, 0.75);
-        let edu_p90 = percentile(&educations, 0.90);
 
-        let adult_agents = agents
-            .iter()
-            .filter(|a| a.age >= 18 * 12 && a.alive)
-            .count();
-
-        let mut file = OpenOptions::new()
-            .append(true)
-            .open(&self.file_path)
-            .expect("Failed to open metrics file");
-        writeln!(
-        file,
-        "{},{:.2},{},{:.5},{:.2},{:.2},{:.2},{:.2},{:.2},{:.2},{:.2},{:.2},{},{:.2},{:.2},{:.2},{:.2},{:.2},{:.2},{:.2},{:.2}",
-        iteration, iteration_total_transaction_amount, iteration_total_transaction_count,
-        gini, min, p10, p25, p50, p75, p90, max, total_wealth, adult_agents,
-        edu_mean, edu_min, edu_p10, edu_p25, edu_p50, edu_p75, edu_p90, edu_max
-    )
-    .unwrap();
+        Some(Self {
+            iteration,
+            total_transactions_amount,
+            transactions_count,
+            gini,
+            min: *wealths.first().unwrap_or(&0.0),
+            p10: percentile(&wealths, 0.10),
+            p25: percentile(&wealths, 0.25),
+            p50,
+            p75: percentile(&wealths, 0.75),
+            p90: percentile(&wealths, 0.90),
+            max: *wealths.last().unwrap_or(&0.0),
+            total_wealth,
+            adult_agents,
+            edu_mean,
+            edu_min: *educations.first().unwrap_or(&0.0),
+            edu_p10: percentile(&educations, 0.10),
+            edu_p25: percentile(&educations, 0.25),
+            edu_p50: percentile(&educations, 0.50),
+            edu_p75: percentile(&educations, 0.75),
+            edu_p90: percentile(&educations, 0.90),
+            edu_max: *educations.last().unwrap_or(&0.0),
+            top10_share,
+            median_mean_ratio,
+            tax_revenue: budget.revenue(),
+            transaction_tax_revenue: budget.transaction_tax,
+            income_tax_revenue: budget.income_tax,
+            estate_tax_revenue: budget.estate_tax,
+            inheritance_tax_revenue: budget.inheritance_tax,
+            estate_tax_rate: budget.estate_tax_rate(),
+            inheritance_tax_rate: budget.inheritance_tax_rate(),
+            government_spending: budget.spending,
+            government_balance: budget.balance,
+        })
     }
 
     fn gini(wealths: &[f64]) -> f64 {
@@ -115,3 +339,373 @@ impl Metrics {
         }
     }
 }
+
+impl Metrics {
+    pub fn new(file_path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(file_path)?;
+        let mut file = BufWriter::new(file);
+
+        writeln!(file, "iteration,{}", Stats::COLUMN_NAMES.join(","))?;
+
+        Ok(Self {
+            writer: Writer::File(file),
+            log_every: 1,
+            error: None,
+        })
+    }
+
+    /// Continues an existing metrics file from `iteration`, dropping any rows
+    /// written after it, e.g. by a run that crashed after its last checkpoint.
+    /// Starts a new file if there is none.
+    pub fn resume(file_path: &str, iteration: usize) -> io::Result<Self> {
+        let Ok(content) = fs::read_to_string(file_path) else {
+            return Self::new(file_path);
+        };
+        let kept: String = content
+            .lines()
+            .filter(|line| {
+                line.split(',')
+                    .next()
+                    .and_then(|i| i.parse::<usize>().ok())
+                    .is_none_or(|i| i <= iteration)
+            })
+            .map(|line| format!("{}\n", line))
+            .collect();
+        fs::write(file_path, kept)?;
+        let file = OpenOptions::new().append(true).open(file_path)?;
+
+        Ok(Self {
+            writer: Writer::File(BufWriter::new(file)),
+            log_every: 1,
+            error: None,
+        })
+    }
+
+    /// Only write every `log_every`-th iteration to the file.
+    pub fn with_interval(mut self, log_every: usize) -> Self {
+        self.log_every = log_every.max(1);
+        self
+    }
+
+    /// Computes and writes the statistics on a separate thread from snapshots
+    /// of the agents, so that steps do not wait for sorting or the disk unless
+    /// the thread falls several snapshots behind.
+    pub fn in_background(mut self) -> Self {
+        let stopped = Writer::Background {
+            messages: None,
+            thread: None,
+        };
+        self.writer = match std::mem::replace(&mut self.writer, stopped) {
+            Writer::File(file) => Self::spawn_writer(file),
+            background => background,
+        };
+        self
+    }
+
+    /// The thread keeps the first error, skips the rows after it and returns it
+    /// when asked to flush and when it stops.
+    fn spawn_writer(mut file: BufWriter<File>) -> Writer {
+        let (messages, receiver) = mpsc::sync_channel(BACKGROUND_QUEUE);
+        let thread = thread::spawn(move || {
+            let mut error = None;
+            for message in receiver {
+                let written = match message {
+                    Message::Stats(stats) if error.is_none() => Self::write(&mut file, &stats),
+                    Message::Snapshot(snapshot) if error.is_none() => {
+                        Stats::from_snapshot(snapshot)
+                            .map_or(Ok(()), |stats| Self::write(&mut file, &stats))
+                    }
+                    Message::Flush(done) => {
+                        if error.is_none() {
+                            error = file.flush().err();
+                        }
+                        let _ = done.send(error.as_ref().map_or(Ok(()), |e| Err(copy(e))));
+                        Ok(())
+                    }
+                    _ => Ok(()),
+                };
+                if let Err(e) = written {
+                    error.get_or_insert(e);
+                }
+            }
+            match error {
+                Some(e) => Err(e),
+                None => file.flush(),
+            }
+        });
+        Writer::Background {
+            messages: Some(messages),
+            thread: Some(thread),
+        }
+    }
+
+    pub fn log(
+        &mut self,
+        iteration: usize,
+        agents: &[Agent],
+        iteration_total_transaction_amount: f64,
+        iteration_total_transaction_count: u32,
+        budget: Budget,
+    ) {
+        self.record_snapshot(Snapshot::take(
+            iteration,
+            agents,
+            iteration_total_transaction_amount,
+            iteration_total_transaction_count,
+            budget,
+        ));
+    }
+
+    /// Flushes the file, stops a background writer and reports the first error
+    /// either ran into.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush()?;
+        if let Writer::Background { messages, thread } = &mut self.writer {
+            messages.take();
+            if let Some(thread) = thread.take() {
+                thread.join().unwrap_or_else(|_| Err(writer_stopped()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends `message` to the background writer, noting an error instead if the
+    /// writer has stopped.
+    fn send(&mut self, message: Message) {
+        if let Writer::Background {
+            messages: Some(messages),
+            ..
+        } = &self.writer
+        {
+            if messages.send(message).is_err() {
+                self.error.get_or_insert_with(writer_stopped);
+            }
+        }
+    }
+
+    fn write(file: &mut BufWriter<File>, stats: &Stats) -> io::Result<()> {
+        let mut row = stats.iteration.to_string();
+        for (value, decimals) in stats.values().iter().zip(Stats::COLUMN_DECIMALS) {
+            row.push_str(&format!(",{:.*}", decimals, value));
+        }
+        writeln!(file, "{}", row)
+    }
+
+    /// Writes `stats` to the file of a foreground writer, unless it has failed before.
+    fn write_row(&mut self, stats: &Stats) {
+        if let (Writer::File(file), None) = (&mut self.writer, &self.error) {
+            if let Err(e) = Self::write(file, stats) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+/// Same kind and message as `error`, which cannot be cloned.
+fn copy(error: &io::Error) -> io::Error {
+    io::Error::new(error.kind(), error.to_string())
+}
+
+fn writer_stopped() -> io::Error {
+    io::Error::other("metrics writer thread stopped")
+}
+
+impl MetricsSink for Metrics {
+    fn record(&mut self, stats: &Stats) {
+        if !self.wants(stats.iteration) {
+            return;
+        }
+        match &mut self.writer {
+            Writer::File(_) => self.write_row(stats),
+            Writer::Background { .. } => self.send(Message::Stats(*stats)),
+        }
+    }
+
+    fn wants(&self, iteration: usize) -> bool {
+        iteration % self.log_every == 0
+    }
+
+    fn takes_snapshots(&self) -> bool {
+        matches!(self.writer, Writer::Background { .. })
+    }
+
+    fn record_snapshot(&mut self, snapshot: Snapshot) {
+        if !self.wants(snapshot.iteration) {
+            return;
+        }
+        match &mut self.writer {
+            Writer::File(_) => {
+                if let Some(stats) = Stats::from_snapshot(snapshot) {
+                    self.write_row(&stats);
+                }
+            }
+            Writer::Background { .. } => self.send(Message::Snapshot(snapshot)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.error.is_none() {
+            let flushed = match &mut self.writer {
+                Writer::File(file) => file.flush(),
+                Writer::Background { .. } => {
+                    let (done, written) = mpsc::sync_channel(1);
+                    self.send(Message::Flush(done));
+                    written.recv().unwrap_or_else(|_| Err(writer_stopped()))
+                }
+            };
+            if let Err(e) = flushed {
+                self.error.get_or_insert(e);
+            }
+        }
+        self.error.as_ref().map_or(Ok(()), |e| Err(copy(e)))
+    }
+}
+
+impl Drop for Metrics {
+    /// Waits for a background writer to write the remaining snapshots.
+    fn drop(&mut self) {
+        if let Writer::Background { messages, thread } = &mut self.writer {
+            messages.take();
+            if let Some(thread) = thread.take() {
+                if thread.join().is_err() && !thread::panicking() {
+                    eprintln!("Warning: metrics writer thread failed");
+                }
+            }
+        }
+    }
+}
+
+/// Sample mean and standard deviation; the deviation is 0 for a single value.
+pub(crate) fn mean_and_std(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if values.len() < 2 {
+        return (mean, 0.0);
+    }
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, variance.sqrt())
+}
+
+/// Linearly interpolated quantile of sorted values.
+pub(crate) fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(iteration: usize) -> Snapshot {
+        let n = 20 + iteration;
+        Snapshot {
+            iteration,
+            wealths: (0..n).map(|i| ((i * 7919) % 101) as f64 / 3.0).collect(),
+            educations: (0..n).map(|i| (i % 9) as f64 * 0.5).collect(),
+            adult_agents: n - 3,
+            total_transactions_amount: iteration as f64 * 1.25,
+            transactions_count: iteration as u32 * 3,
+            budget: Budget {
+                balance: 10.0,
+                transaction_tax: 0.5,
+                ..Budget::default()
+            },
+        }
+    }
+
+    fn scratch_file(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("wds_metrics_{}_{}.csv", name, std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Feeds iterations 1 to 20 to `metrics` as a run would and returns the file.
+    fn write_run(path: &str, mut metrics: Metrics) -> String {
+        for iteration in 1..=20 {
+            if metrics.wants(iteration) {
+                if metrics.takes_snapshots() {
+                    metrics.record_snapshot(snapshot(iteration));
+                } else {
+                    metrics.record(&Stats::from_snapshot(snapshot(iteration)).unwrap());
+                }
+            }
+        }
+        metrics.finish().unwrap();
+        let content = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+        content
+    }
+
+    #[test]
+    fn only_every_interval_th_iteration_is_written() {
+        let path = scratch_file("interval");
+        let content = write_run(&path, Metrics::new(&path).unwrap().with_interval(6));
+        let iterations: Vec<&str> = content
+            .lines()
+            .map(|line| line.split(',').next().unwrap())
+            .collect();
+        assert_eq!(iterations, ["iteration", "6", "12", "18"]);
+
+        // Rows the sink does not want are dropped even when recorded directly
+        let mut metrics = Metrics::new(&path).unwrap().with_interval(4);
+        assert!(!metrics.wants(5) && metrics.wants(8));
+        metrics.record(&Stats::from_snapshot(snapshot(5)).unwrap());
+        metrics.finish().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn background_output_is_identical_to_foreground_output() {
+        for interval in [1, 3] {
+            let foreground = scratch_file("foreground");
+            let background = scratch_file("background");
+            let expected = write_run(
+                &foreground,
+                Metrics::new(&foreground).unwrap().with_interval(interval),
+            );
+            let written = write_run(
+                &background,
+                Metrics::new(&background)
+                    .unwrap()
+                    .with_interval(interval)
+                    .in_background(),
+            );
+            assert_eq!(written.lines().count(), 1 + 20 / interval);
+            assert_eq!(written, expected);
+        }
+    }
+
+    #[test]
+    fn missing_directories_are_reported_when_creating_the_file() {
+        let path = std::env::temp_dir().join("wds_no_such_directory/metrics.csv");
+        assert!(Metrics::new(&path.to_string_lossy()).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn write_errors_are_returned_instead_of_panicking() {
+        // Writes to /dev/full fail once the buffer is flushed
+        for background in [false, true] {
+            let mut metrics = Metrics::new("/dev/full").unwrap();
+            if background {
+                metrics = metrics.in_background();
+            }
+            for iteration in 1..=200 {
+                metrics.record_snapshot(snapshot(iteration));
+            }
+            assert!(metrics.flush().is_err());
+            // Later rows are skipped and the error stays
+            metrics.record_snapshot(snapshot(201));
+            assert!(metrics.flush().is_err());
+            assert!(metrics.finish().is_err());
+        }
+    }
+}
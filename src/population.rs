@@ -0,0 +1,281 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::agent::{Agent, AgentId};
+use crate::agent_store::AgentStore;
+use crate::config_error::{ConfigError, ValidationIssue};
+use crate::config_file;
+use crate::environment_config::EnvironmentConfig;
+
+/// Columns of a population CSV, in the order they are written.
+const COLUMNS: [&str; 7] = ["id", "age", "wealth", "education", "x", "y", "parents"];
+
+/// One agent of an initial population file.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AgentRecord {
+    pub id: usize,
+    /// Age in years.
+    pub age: f64,
+    pub wealth: f64,
+    pub education: f64,
+    /// Position; drawn uniformly within the world when absent.
+    #[serde(default)]
+    pub x: Option<f64>,
+    #[serde(default)]
+    pub y: Option<f64>,
+    /// Ids of the agent's parents within the same file.
+    #[serde(default)]
+    pub parents: Vec<usize>,
+}
+
+/// Reads and validates a population from a CSV file, or from a JSON or YAML
+/// array of agents.
+pub fn read(path: &Path, config: &EnvironmentConfig) -> Result<Vec<AgentRecord>, ConfigError> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let records = match extension.as_deref() {
+        Some("csv") => read_csv(path)?,
+        _ => config_file::read_document(path)?,
+    };
+    validate(&records, config)?;
+    Ok(records)
+}
+
+fn read_csv(path: &Path) -> Result<Vec<AgentRecord>, ConfigError> {
+    let content = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.display().to_string(),
+        source,
+    })?;
+    let parse_error = |line: usize, message: String| ConfigError::Parse {
+        path: path.display().to_string(),
+        message: format!("line {}: {}", line, message),
+    };
+
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty());
+    let header: Vec<&str> = match lines.next() {
+        Some((_, header)) => header.split(',').map(str::trim).collect(),
+        None => return Err(parse_error(1, "missing header".to_string())),
+    };
+    if let Some(unknown) = header.iter().find(|h| !COLUMNS.contains(h)) {
+        return Err(parse_error(
+            1,
+            format!(
+                "unknown column `{}`, expected {}",
+                unknown,
+                COLUMNS.join(", ")
+            ),
+        ));
+    }
+
+    lines
+        .map(|(index, line)| {
+            let line_number = index + 1;
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if fields.len() != header.len() {
+                return Err(parse_error(
+                    line_number,
+                    format!("expected {} fields, found {}", header.len(), fields.len()),
+                ));
+            }
+            let row: HashMap<&str, &str> = header.iter().copied().zip(fields).collect();
+            let number = |column: &str| -> Result<Option<f64>, ConfigError> {
+                match row.get(column).filter(|v| !v.is_empty()) {
+                    None => Ok(None),
+                    Some(v) => v.parse().map(Some).map_err(|_| {
+                        parse_error(line_number, format!("`{}` is not a number: {}", column, v))
+                    }),
+                }
+            };
+            let required = |column: &str| -> Result<f64, ConfigError> {
+                number(column)?
+                    .ok_or_else(|| parse_error(line_number, format!("missing `{}`", column)))
+            };
+            let parents = row
+                .get("parents")
+                .map(|v| {
+                    v.split(';')
+                        .map(str::trim)
+                        .filter(|p| !p.is_empty())
+                        .map(|p| {
+                            p.parse().map_err(|_| {
+                                parse_error(line_number, format!("`{}` is not a parent id", p))
+                            })
+                        })
+                        .collect::<Result<Vec<usize>, _>>()
+                })
+                .transpose()?
+                .unwrap_or_default();
+            let id = row
+                .get("id")
+                .ok_or_else(|| parse_error(line_number, "missing `id`".to_string()))?;
+            Ok(AgentRecord {
+                id: id
+                    .parse()
+                    .map_err(|_| parse_error(line_number, format!("`{}` is not an id", id)))?,
+                age: required("age")?,
+                wealth: required("wealth")?,
+                education: required("education")?,
+                x: number("x")?,
+                y: number("y")?,
+                parents,
+            })
+        })
+        .collect()
+}
+
+/// Checks values, positions and parent links, reporting every problem with the
+/// index of its record, e.g. `agents.3.x`.
+fn validate(records: &[AgentRecord], config: &EnvironmentConfig) -> Result<(), ConfigError> {
+    let mut issues = Vec::new();
+    let mut issue = |path: String, constraint: String| {
+        issues.push(ValidationIssue { path, constraint });
+    };
+
+    if records.len() < 2 {
+        issue(
+            "agents".to_string(),
+            "must contain at least 2 agents".to_string(),
+        );
+    }
+    let reproductive = records
+        .iter()
+        .filter(|r| r.age > 20.0 && r.age < 60.0)
+        .count();
+    if reproductive < 2 {
+        issue(
+            "agents".to_string(),
+            "must contain at least 2 agents aged between 20 and 60 to have offspring".to_string(),
+        );
+    }
+
+    let mut ids = HashSet::new();
+    for (i, r) in records.iter().enumerate() {
+        if !ids.insert(r.id) {
+            issue(
+                format!("agents.{}.id", i),
+                format!("{} is not unique", r.id),
+            );
+        }
+        if !(r.age.is_finite() && r.age >= 0.0) {
+            issue(
+                format!("agents.{}.age", i),
+                "must be at least 0".to_string(),
+            );
+        }
+        if !r.wealth.is_finite() {
+            issue(
+                format!("agents.{}.wealth", i),
+                "must be a finite number".to_string(),
+            );
+        }
+        if !(r.education.is_finite() && r.education >= 0.0) {
+            issue(
+                format!("agents.{}.education", i),
+                "must be at least 0".to_string(),
+            );
+        }
+        for (field, value, max) in [("x", r.x, config.length), ("y", r.y, config.width)] {
+            if value.is_some_and(|v| !(0.0..=max as f64).contains(&v)) {
+                issue(
+                    format!("agents.{}.{}", i, field),
+                    format!("must be within [0, {}]", max),
+                );
+            }
+        }
+    }
+    for (i, r) in records.iter().enumerate() {
+        for parent in &r.parents {
+            if *parent == r.id || !ids.contains(parent) {
+                issue(
+                    format!("agents.{}.parents", i),
+                    format!("{} is not the id of another agent", parent),
+                );
+            }
+        }
+    }
+
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid(issues))
+    }
+}
+
+/// Creates the agents of validated records and links parents to their children.
+/// Missing positions are drawn from `rng`.
+pub(crate) fn agents(
+    records: &[AgentRecord],
+    config: &EnvironmentConfig,
+    rng: &mut impl Rng,
+) -> AgentStore {
+    let agents: Vec<Agent> = records
+        .iter()
+        .map(|r| {
+            let mut agent = Agent::newborn(
+                AgentId(r.id),
+                r.x.unwrap_or_else(|| rng.gen_range(0.0..config.length as f64)),
+                r.y.unwrap_or_else(|| rng.gen_range(0.0..config.width as f64)),
+                config.age_and_death.mid_age,
+                config.age_and_death.steepness,
+            );
+            agent.wealth = r.wealth;
+            agent.education = r.education;
+            agent.age = (r.age * 12.0).round() as u32;
+            agent
+        })
+        .collect();
+
+    let mut agents = AgentStore::new(agents);
+    for r in records {
+        for parent in &r.parents {
+            agents
+                .get_mut(AgentId(*parent))
+                .expect("Parents are validated to exist")
+                .children
+                .push(AgentId(r.id));
+        }
+    }
+    agents
+}
+
+/// Writes the living agents as a population CSV that [`read`] accepts, with
+/// parent links rebuilt from the `children` lists.
+pub fn write_csv(path: &Path, agents: &[Agent]) -> Result<(), ConfigError> {
+    let mut parents: HashMap<AgentId, Vec<AgentId>> = HashMap::new();
+    for agent in agents.iter().filter(|a| a.alive) {
+        for child in &agent.children {
+            parents.entry(*child).or_default().push(agent.id());
+        }
+    }
+
+    let mut content = COLUMNS.join(",") + "\n";
+    for agent in agents.iter().filter(|a| a.alive) {
+        let agent_parents: Vec<String> = parents
+            .get(&agent.id())
+            .map(|p| p.iter().map(|id| id.to_string()).collect())
+            .unwrap_or_default();
+        content.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            agent.id(),
+            agent.age as f64 / 12.0,
+            agent.wealth,
+            agent.education,
+            agent.x,
+            agent.y,
+            agent_parents.join(";")
+        ));
+    }
+    fs::write(path, content).map_err(|source| ConfigError::Io {
+        path: path.display().to_string(),
+        source,
+    })
+}
@@ -0,0 +1,133 @@
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::config_error::ConfigError;
+use crate::environment::Environment;
+use crate::environment_config::EnvironmentConfig;
+use crate::metrics::{mean_and_std, quantile, Stats};
+
+/// Statistics of one replicate, one entry per logged iteration.
+pub type Trajectory = Vec<Stats>;
+
+/// Seeds of `count` replicates starting from `base`: `base`, `base + 1`, ...
+fn replicate_seeds(base: u64, count: usize) -> Vec<u64> {
+    (0..count as u64).map(|i| base.wrapping_add(i)).collect()
+}
+
+/// Runs `config.replicates` seeded copies of `config` in parallel and returns the
+/// base seed together with their statistics, logged every `log_every` iterations.
+/// Unseeded configs start from a random base seed; replicate `i` uses `base + i`.
+pub fn run_replicates(
+    config: &EnvironmentConfig,
+    log_every: usize,
+) -> Result<(u64, Vec<Trajectory>), ConfigError> {
+    let log_every = log_every.max(1);
+    let base = config.seed.unwrap_or_else(|| rand::random::<u32>() as u64);
+    let trajectories = replicate_seeds(base, config.replicates)
+        .into_par_iter()
+        .map(|seed| {
+            let mut config = config.clone();
+            config.seed = Some(seed);
+            let mut env = Environment::try_new(&config)?;
+            let mut trajectory = Vec::new();
+            while !env.finished() {
+                env.step()?;
+                if env.iteration() % log_every == 0 {
+                    trajectory.extend(env.stats());
+                }
+            }
+            Ok(trajectory)
+        })
+        .collect::<Result<_, ConfigError>>()?;
+    Ok((base, trajectories))
+}
+
+/// Writes the mean, standard deviation and 5th/95th percentile across replicates
+/// of every metrics column, one row per iteration. Iterations where a replicate
+/// has no living agents are aggregated over the remaining replicates.
+pub fn write_aggregate(path: &str, trajectories: &[Trajectory]) -> io::Result<()> {
+    let mut by_iteration: BTreeMap<usize, Vec<&Stats>> = BTreeMap::new();
+    for stats in trajectories.iter().flatten() {
+        by_iteration.entry(stats.iteration).or_default().push(stats);
+    }
+
+    let mut file = BufWriter::new(File::create(path)?);
+    let mut header = String::from("iteration,replicates");
+    if let Some(stats) = trajectories.iter().flatten().next() {
+        for (name, _) in stats.columns() {
+            for suffix in ["mean", "std", "p05", "p95"] {
+                header.push_str(&format!(",{}_{}", name, suffix));
+            }
+        }
+    }
+    writeln!(file, "{}", header)?;
+
+    for (iteration, rows) in by_iteration {
+        write!(file, "{},{}", iteration, rows.len())?;
+        let columns: Vec<_> = rows.iter().map(|s| s.columns()).collect();
+        for c in 0..columns[0].len() {
+            let mut values: Vec<f64> = columns.iter().map(|row| row[c].1).collect();
+            values.sort_by(|a, b| a.total_cmp(b));
+            let (mean, std) = mean_and_std(&values);
+            write!(
+                file,
+                ",{:.5},{:.5},{:.5},{:.5}",
+                mean,
+                std,
+                quantile(&values, 0.05),
+                quantile(&values, 0.95)
+            )?;
+        }
+        writeln!(file)?;
+    }
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stats at `iteration` with every column equal to `value`.
+    fn stats(iteration: usize, value: u32) -> Stats {
+        let mut fields = serde_json::Map::new();
+        fields.insert("iteration".to_string(), iteration.into());
+        for name in Stats::COLUMN_NAMES {
+            fields.insert(name.to_string(), value.into());
+        }
+        serde_json::from_value(fields.into()).unwrap()
+    }
+
+    #[test]
+    fn aggregate_rows_hold_mean_std_and_percentiles_across_replicates() {
+        // The third replicate has no living agents at iteration 20
+        let trajectories = vec![
+            vec![stats(10, 1), stats(20, 4)],
+            vec![stats(10, 2), stats(20, 8)],
+            vec![stats(10, 3)],
+        ];
+        let path = std::env::temp_dir().join(format!("wds_aggregate_{}.csv", std::process::id()));
+        write_aggregate(path.to_str().unwrap(), &trajectories).unwrap();
+        let output = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with(
+            "iteration,replicates,total_transactions_amount_mean,total_transactions_amount_std,\
+             total_transactions_amount_p05,total_transactions_amount_p95,transactions_count_mean"
+        ));
+        let expected_rows = [
+            ("10,3", "2.00000,1.00000,1.10000,2.90000"),
+            ("20,2", "6.00000,2.82843,4.20000,7.80000"),
+        ];
+        for (line, (prefix, aggregate)) in lines[1..].iter().zip(expected_rows) {
+            let expected = std::iter::once(prefix)
+                .chain(std::iter::repeat_n(aggregate, Stats::COLUMN_COUNT))
+                .collect::<Vec<_>>()
+                .join(",");
+            assert_eq!(*line, expected);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+
+use crate::config_error::{ConfigError, ValidationIssue};
+use crate::environment_config::EnvironmentConfig;
+
+/// Fields that are only read when the environment is created, or that describe
+/// the run itself, so changing them mid-run would have no effect.
+const UNSCHEDULABLE_PATHS: &[&str] = &[
+    "schema_version",
+    "num_iterations",
+    "num_agents",
+    "length",
+    "width",
+    "seed",
+    "replicates",
+    "initial_population",
+];
+
+/// Fields, or whole sections, that agents copy or use only when they are
+/// created, so a change reaches no existing or newborn agent.
+const CREATION_TIME_PATHS: &[&str] = &[
+    "age_and_death",
+    "education.initial_adult_min",
+    "education.initial_adult_max",
+    "education.elemental_education_threshold",
+    "education.children_education_jitter",
+    "wealth.min_initial_wealth",
+    "wealth.max_initial_wealth",
+];
+
+/// A change of one config field during a run. Without `until` the field is set
+/// to `value` before iteration `at` runs; with `until` it ramps linearly from its
+/// value at `at` to `value` at `until`.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+pub struct ScheduleEntry {
+    /// Dotted path of the field, e.g. `transaction.tax_rate`.
+    pub path: String,
+    /// Iteration before which the change starts.
+    pub at: usize,
+    /// Iteration at which a ramp reaches `value`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<usize>,
+    pub value: Value,
+}
+
+impl ScheduleEntry {
+    /// Value the field takes before iteration `iteration` runs, if this entry
+    /// changes it then. Ramps step from the field's current value so that
+    /// `value` is reached exactly at `until`.
+    pub fn value_at(&self, iteration: usize, config: &EnvironmentConfig) -> Option<Value> {
+        if !self.changes_at(iteration) {
+            return None;
+        }
+        match self.until {
+            None => self.next_value(iteration, &Value::Null),
+            Some(_) => self.next_value(iteration, &config.get_path(&self.path).ok()?),
+        }
+    }
+
+    fn changes_at(&self, iteration: usize) -> bool {
+        match self.until {
+            None => iteration == self.at,
+            Some(until) => iteration > self.at && iteration <= until,
+        }
+    }
+
+    /// Value of the field after this entry's change before `iteration`, given
+    /// its `current` value; only ramps depend on it.
+    fn next_value(&self, iteration: usize, current: &Value) -> Option<Value> {
+        match self.until {
+            None => Some(self.value.clone()),
+            Some(until) => {
+                let from = current.as_f64()?;
+                let to = self.value.as_f64()?;
+                let next = from + (to - from) / (until - iteration + 1) as f64;
+                if current.is_f64() {
+                    Some(Value::from(next))
+                } else {
+                    Some(Value::from(next.round() as i64))
+                }
+            }
+        }
+    }
+
+    /// Checks that the entry names a field that can change during a run and that
+    /// its value fits the field, reporting issues under `prefix`, e.g.
+    /// `schedule.0`. Constraints on the resulting configs are checked by
+    /// [`validate_timeline`].
+    pub(crate) fn validate(
+        &self,
+        prefix: &str,
+        config: &EnvironmentConfig,
+        issues: &mut Vec<ValidationIssue>,
+    ) {
+        let mut issue = |field: &str, constraint: String| {
+            issues.push(ValidationIssue {
+                path: format!("{}.{}", prefix, field),
+                constraint,
+            });
+        };
+        if !changeable_during_run(&self.path) {
+            issue(
+                "path",
+                format!("`{}` cannot change during a run", self.path),
+            );
+            return;
+        }
+        if read_at_creation(&self.path) {
+            issue(
+                "path",
+                format!(
+                    "`{}` is only read when agents are created, so changing it during a run has no effect",
+                    self.path
+                ),
+            );
+            return;
+        }
+        let current = match config.get_path(&self.path) {
+            Ok(current) if !current.is_object() => current,
+            _ => {
+                issue("path", format!("`{}` is not a config field", self.path));
+                return;
+            }
+        };
+        let mut scheduled = config.clone();
+        scheduled.schedule.clear();
+        match scheduled.set_path(&self.path, self.value.clone()) {
+            Err(ConfigError::Override { message, .. }) => issue("value", message),
+            Err(e) => issue("value", e.to_string()),
+            Ok(()) => {}
+        }
+        if let Some(until) = self.until {
+            if until <= self.at {
+                issue("until", "must be greater than at".to_string());
+            }
+            if !current.is_number() || !self.value.is_number() {
+                issue("value", "ramps need a numeric field and value".to_string());
+            }
+        }
+    }
+}
+
+/// Replays the schedule of `config` and validates the config it has built up
+/// before every iteration at which an entry starts or ends, reporting each
+/// broken constraint under `schedule` the first time it breaks. Ramps stay
+/// between their endpoints, so checking those covers them.
+pub(crate) fn validate_timeline(config: &EnvironmentConfig, issues: &mut Vec<ValidationIssue>) {
+    let mut checked: Vec<usize> = config
+        .schedule
+        .iter()
+        .flat_map(|entry| [Some(entry.at), entry.until])
+        .flatten()
+        .collect();
+    checked.sort_unstable();
+    checked.dedup();
+    let Some(&last) = checked.last() else {
+        return;
+    };
+
+    let mut fields = serde_json::to_value(config).expect("Failed to serialize config");
+    let mut reported = HashSet::new();
+    for iteration in 0..=last {
+        for entry in config.schedule.iter().filter(|e| e.changes_at(iteration)) {
+            let Ok(field) = EnvironmentConfig::field_mut(&mut fields, &entry.path) else {
+                return; // reported by the entry
+            };
+            if let Some(value) = entry.next_value(iteration, field) {
+                *field = value;
+            }
+        }
+        if checked.binary_search(&iteration).is_err() {
+            continue;
+        }
+
+        let invalid = match serde_json::from_value::<EnvironmentConfig>(fields.clone()) {
+            Ok(scheduled) => match scheduled.validate_fields() {
+                Err(ConfigError::Invalid(invalid)) => invalid,
+                _ => Vec::new(),
+            },
+            Err(e) => vec![ValidationIssue {
+                path: "schedule".to_string(),
+                constraint: e.to_string(),
+            }],
+        };
+        for v in invalid {
+            if reported.insert((v.path.clone(), v.constraint.clone())) {
+                issues.push(ValidationIssue {
+                    path: "schedule".to_string(),
+                    constraint: format!(
+                        "makes `{}` invalid before iteration {}: {}",
+                        v.path, iteration, v.constraint
+                    ),
+                });
+            }
+        }
+    }
+}
+
+/// Whether agents only read the field at `path` when they are created.
+pub(crate) fn read_at_creation(path: &str) -> bool {
+    CREATION_TIME_PATHS.iter().any(|p| {
+        path == *p
+            || path
+                .strip_prefix(p)
+                .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+/// Whether the field at `path` may change once the environment exists. Schedules
+/// and convergence criteria cannot change themselves either.
+pub(crate) fn changeable_during_run(path: &str) -> bool {
+    let root = path.split('.').next().unwrap_or_default();
+    !UNSCHEDULABLE_PATHS.contains(&path) && root != "schedule" && root != "convergence"
+}
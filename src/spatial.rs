@@ -0,0 +1,130 @@
+/// Uniform grid over the world for finding the points near a location. Cells are
+/// at least `radius` wide, so all points within `radius` of a location lie in its
+/// cell or one of the eight around it.
+pub(crate) struct SpatialGrid {
+    cell_size: f64,
+    columns: usize,
+    rows: usize,
+    cell_starts: Vec<usize>, // `cell_starts[c]..cell_starts[c + 1]` indexes `points` of cell `c`
+    points: Vec<usize>,
+}
+
+impl SpatialGrid {
+    /// Indexes `positions` within `[0, max_x] x [0, max_y]`. The cell size is
+    /// raised above `radius` when needed to keep the grid at most about as large
+    /// as the number of points.
+    pub(crate) fn new(positions: &[(f64, f64)], max_x: f64, max_y: f64, radius: f64) -> Self {
+        let min_cell_size = (max_x * max_y / positions.len().max(1) as f64).sqrt();
+        let cell_size = radius.max(min_cell_size).max(f64::MIN_POSITIVE);
+        let columns = ((max_x / cell_size).ceil() as usize).max(1);
+        let rows = ((max_y / cell_size).ceil() as usize).max(1);
+
+        let mut grid = Self {
+            cell_size,
+            columns,
+            rows,
+            cell_starts: vec![0; columns * rows + 1],
+            points: vec![0; positions.len()],
+        };
+        // Counting sort of the points by cell
+        let cells: Vec<usize> = positions.iter().map(|&(x, y)| grid.cell(x, y)).collect();
+        for &cell in &cells {
+            grid.cell_starts[cell + 1] += 1;
+        }
+        for c in 0..columns * rows {
+            grid.cell_starts[c + 1] += grid.cell_starts[c];
+        }
+        let mut next = grid.cell_starts.clone();
+        for (point, &cell) in cells.iter().enumerate() {
+            grid.points[next[cell]] = point;
+            next[cell] += 1;
+        }
+        grid
+    }
+
+    fn cell(&self, x: f64, y: f64) -> usize {
+        let column = ((x / self.cell_size).max(0.0) as usize).min(self.columns - 1);
+        let row = ((y / self.cell_size).max(0.0) as usize).min(self.rows - 1);
+        row * self.columns + column
+    }
+
+    /// Calls `f` with the index of every point in the cells around `(x, y)`,
+    /// a superset of the points within `radius`.
+    pub(crate) fn for_each_candidate(&self, x: f64, y: f64, mut f: impl FnMut(usize)) {
+        let cell = self.cell(x, y);
+        let (column, row) = (cell % self.columns, cell / self.columns);
+        for r in row.saturating_sub(1)..=(row + 1).min(self.rows - 1) {
+            let first = r * self.columns + column.saturating_sub(1);
+            let last = r * self.columns + (column + 1).min(self.columns - 1);
+            for &point in &self.points[self.cell_starts[first]..self.cell_starts[last + 1]] {
+                f(point);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    /// Pairs `(i, j)`, `i < j`, at most `radius` apart, found through the grid.
+    fn grid_pairs(positions: &[(f64, f64)], max: f64, radius: f64) -> Vec<(usize, usize)> {
+        let grid = SpatialGrid::new(positions, max, max, radius);
+        let mut pairs = Vec::new();
+        for (i, &(ax, ay)) in positions.iter().enumerate() {
+            grid.for_each_candidate(ax, ay, |j| {
+                let (bx, by) = positions[j];
+                if j > i && ((ax - bx).powi(2) + (ay - by).powi(2)).sqrt() <= radius {
+                    pairs.push((i, j));
+                }
+            });
+        }
+        pairs.sort_unstable();
+        pairs
+    }
+
+    fn brute_force_pairs(positions: &[(f64, f64)], radius: f64) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for (i, &(ax, ay)) in positions.iter().enumerate() {
+            for (j, &(bx, by)) in positions.iter().enumerate().skip(i + 1) {
+                if ((ax - bx).powi(2) + (ay - by).powi(2)).sqrt() <= radius {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    #[test]
+    fn grid_finds_the_same_pairs_as_a_brute_force_scan() {
+        // A lattice with points on every cell edge, 100 x 100 world and 10 wide
+        // cells, so that lattice neighbours are exactly `radius` apart
+        let lattice: Vec<(f64, f64)> = (0..=20)
+            .flat_map(|i| (0..=20).map(move |j| (i as f64 * 5.0, j as f64 * 5.0)))
+            .collect();
+        assert_eq!(
+            SpatialGrid::new(&lattice, 100.0, 100.0, 10.0).cell_size,
+            10.0
+        );
+        let lattice_pairs = grid_pairs(&lattice, 100.0, 10.0);
+        assert!(!lattice_pairs.is_empty());
+        assert_eq!(lattice_pairs, brute_force_pairs(&lattice, 10.0));
+
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        for (n, radius) in [(1, 5.0), (50, 3.0), (500, 7.0), (2000, 2.5), (300, 150.0)] {
+            let mut positions: Vec<(f64, f64)> = (0..n)
+                .map(|_| (rng.gen_range(0.0..=100.0), rng.gen_range(0.0..=100.0)))
+                .collect();
+            positions.extend_from_slice(&lattice[..21]);
+            assert_eq!(
+                grid_pairs(&positions, 100.0, radius),
+                brute_force_pairs(&positions, radius),
+                "{} points, radius {}",
+                n,
+                radius
+            );
+        }
+    }
+}
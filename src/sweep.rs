@@ -0,0 +1,197 @@
+use serde_json::Value;
+
+use crate::config_error::ConfigError;
+use crate::environment_config::EnvironmentConfig;
+
+/// A config field and the values a sweep gives it.
+#[derive(Clone, Debug)]
+pub struct SweepParameter {
+    pub path: String,
+    pub values: Vec<Value>,
+}
+
+/// Parameters varied across the runs of a sweep. By default every combination
+/// of values is run (a grid); with `zip` the i-th run takes the i-th value of
+/// every parameter.
+#[derive(Clone, Debug, Default)]
+pub struct Sweep {
+    pub parameters: Vec<SweepParameter>,
+    pub zip: bool,
+}
+
+/// One configuration produced by a sweep.
+pub struct SweepRun {
+    pub index: usize,
+    pub values: Vec<Value>, // in the order of `Sweep::parameters`
+    pub config: EnvironmentConfig,
+}
+
+impl SweepParameter {
+    /// Parses `path=v1,v2,...`, e.g. `transaction.tax_rate=0,0.05,0.1`.
+    pub fn parse(spec: &str) -> Result<Self, ConfigError> {
+        let invalid = |message: &str| ConfigError::Override {
+            path: spec.to_string(),
+            message: message.to_string(),
+        };
+        let (path, values) = spec
+            .split_once('=')
+            .ok_or_else(|| invalid("expected <path>=<value>,<value>,..."))?;
+        let values: Vec<Value> = values
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(|v| serde_json::from_str(v).unwrap_or_else(|_| Value::String(v.to_string())))
+            .collect();
+        if values.is_empty() {
+            return Err(invalid("no values given"));
+        }
+        Ok(Self {
+            path: path.trim().to_string(),
+            values,
+        })
+    }
+}
+
+impl Sweep {
+    pub fn new(parameters: Vec<SweepParameter>, zip: bool) -> Self {
+        Self { parameters, zip }
+    }
+
+    /// Applies every combination to `base` and validates the resulting configs.
+    pub fn runs(&self, base: &EnvironmentConfig) -> Result<Vec<SweepRun>, ConfigError> {
+        self.combinations()?
+            .into_iter()
+            .enumerate()
+            .map(|(index, values)| {
+                let mut config = base.clone();
+                for (parameter, value) in self.parameters.iter().zip(&values) {
+                    config.set_path(&parameter.path, value.clone())?;
+                }
+                config.validate()?;
+                Ok(SweepRun {
+                    index,
+                    values,
+                    config,
+                })
+            })
+            .collect()
+    }
+
+    fn combinations(&self) -> Result<Vec<Vec<Value>>, ConfigError> {
+        if self.zip {
+            let len = self.parameters.first().map_or(1, |p| p.values.len());
+            if let Some(p) = self.parameters.iter().find(|p| p.values.len() != len) {
+                return Err(ConfigError::Override {
+                    path: p.path.clone(),
+                    message: format!(
+                        "has {} values, zipped parameters all need {}",
+                        p.values.len(),
+                        len
+                    ),
+                });
+            }
+            return Ok((0..len)
+                .map(|i| {
+                    self.parameters
+                        .iter()
+                        .map(|p| p.values[i].clone())
+                        .collect()
+                })
+                .collect());
+        }
+
+        let mut combinations = vec![Vec::new()];
+        for parameter in &self.parameters {
+            combinations = combinations
+                .into_iter()
+                .flat_map(|prefix| {
+                    parameter.values.iter().map(move |value| {
+                        let mut combination = prefix.clone();
+                        combination.push(value.clone());
+                        combination
+                    })
+                })
+                .collect();
+        }
+        Ok(combinations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sweep(specs: &[&str], zip: bool) -> Sweep {
+        let parameters = specs
+            .iter()
+            .map(|spec| SweepParameter::parse(spec).unwrap())
+            .collect();
+        Sweep::new(parameters, zip)
+    }
+
+    #[test]
+    fn grids_run_every_combination_with_the_last_parameter_varying_fastest() {
+        let grid = sweep(
+            &["transaction.tax_rate=0,0.1", "num_agents=100,200,300"],
+            false,
+        );
+        assert_eq!(
+            grid.combinations().unwrap(),
+            vec![
+                vec![json!(0), json!(100)],
+                vec![json!(0), json!(200)],
+                vec![json!(0), json!(300)],
+                vec![json!(0.1), json!(100)],
+                vec![json!(0.1), json!(200)],
+                vec![json!(0.1), json!(300)],
+            ]
+        );
+
+        let runs = grid.runs(&EnvironmentConfig::default()).unwrap();
+        assert_eq!(runs.len(), 6);
+        assert_eq!(runs[4].index, 4);
+        assert_eq!(runs[4].config.transaction.tax_rate, 0.1);
+        assert_eq!(runs[4].config.num_agents, 200);
+    }
+
+    #[test]
+    fn zipped_parameters_take_their_values_in_step() {
+        let zipped = sweep(
+            &["transaction.tax_rate=0,0.1,0.2", "num_agents=100,200,300"],
+            true,
+        );
+        assert_eq!(
+            zipped.combinations().unwrap(),
+            vec![
+                vec![json!(0), json!(100)],
+                vec![json!(0.1), json!(200)],
+                vec![json!(0.2), json!(300)],
+            ]
+        );
+        let runs = zipped.runs(&EnvironmentConfig::default()).unwrap();
+        assert_eq!(runs[2].config.transaction.tax_rate, 0.2);
+        assert_eq!(runs[2].config.num_agents, 300);
+    }
+
+    #[test]
+    fn zipped_parameters_must_have_the_same_number_of_values() {
+        let zipped = sweep(
+            &["transaction.tax_rate=0,0.1,0.2", "num_agents=100,200"],
+            true,
+        );
+        match zipped.combinations() {
+            Err(ConfigError::Override { path, message }) => {
+                assert_eq!(path, "num_agents");
+                assert!(message.contains("has 2 values"), "{}", message);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        // The same parameters make a grid without zip
+        let grid = sweep(
+            &["transaction.tax_rate=0,0.1,0.2", "num_agents=100,200"],
+            false,
+        );
+        assert_eq!(grid.combinations().unwrap().len(), 6);
+    }
+}
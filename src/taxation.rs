@@ -0,0 +1,293 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config_error::ValidationIssue;
+
+/// Marginal `rate` on the part of an amount above `threshold`, up to the
+/// threshold of the next bracket.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug)]
+pub struct TaxBracket {
+    #[schemars(range(min = 0.0))]
+    pub threshold: f64,
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub rate: f64,
+}
+
+/// Progressive tax on the monthly income earned from education and age.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct IncomeTax {
+    /// Income exempt from tax each month, before the brackets apply.
+    #[serde(default)]
+    #[schemars(range(min = 0.0))]
+    pub standard_deduction: f64,
+    /// Brackets in increasing order of threshold. Income below the first
+    /// threshold is untaxed.
+    #[schemars(length(min = 1))]
+    pub brackets: Vec<TaxBracket>,
+}
+
+/// Either one rate on the whole taxable amount or marginal brackets.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TaxRates {
+    Flat(#[schemars(range(min = 0.0, max = 1.0))] f64),
+    Brackets(#[schemars(length(min = 1))] Vec<TaxBracket>),
+}
+
+/// Tax on the estate of an agent who dies, before it is split among the heirs.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct EstateTax {
+    /// Estate value exempt from tax.
+    #[serde(default)]
+    #[schemars(range(min = 0.0))]
+    pub exemption: f64,
+    pub rates: TaxRates,
+}
+
+/// Tax paid by heirs, on everything they have inherited over their life, so a
+/// new inheritance is taxed at the rates its recipient has already reached.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct InheritanceTax {
+    /// Lifetime inheritance exempt from tax.
+    #[serde(default)]
+    #[schemars(range(min = 0.0))]
+    pub exemption: f64,
+    pub rates: TaxRates,
+}
+
+impl IncomeTax {
+    /// Tax owed on one month's `income`.
+    pub fn tax(&self, income: f64) -> f64 {
+        bracketed_tax(&self.brackets, income - self.standard_deduction)
+    }
+
+    /// Reports issues under `prefix`, e.g. `income_tax.brackets.1.rate`.
+    pub(crate) fn validate(&self, prefix: &str, issues: &mut Vec<ValidationIssue>) {
+        if !(self.standard_deduction.is_finite() && self.standard_deduction >= 0.0) {
+            issues.push(ValidationIssue {
+                path: format!("{}.standard_deduction", prefix),
+                constraint: "must be at least 0".to_string(),
+            });
+        }
+        validate_brackets(&self.brackets, &format!("{}.brackets", prefix), issues);
+    }
+}
+
+impl TaxRates {
+    /// Tax on `amount`; nothing for amounts of 0 or below.
+    pub fn tax(&self, amount: f64) -> f64 {
+        match self {
+            TaxRates::Flat(rate) => amount.max(0.0) * rate,
+            TaxRates::Brackets(brackets) => bracketed_tax(brackets, amount),
+        }
+    }
+
+    fn validate(&self, prefix: &str, issues: &mut Vec<ValidationIssue>) {
+        match self {
+            TaxRates::Flat(rate) => {
+                if !(0.0..=1.0).contains(rate) {
+                    issues.push(ValidationIssue {
+                        path: format!("{}.flat", prefix),
+                        constraint: "must be within [0, 1]".to_string(),
+                    });
+                }
+            }
+            TaxRates::Brackets(brackets) => {
+                validate_brackets(brackets, &format!("{}.brackets", prefix), issues)
+            }
+        }
+    }
+}
+
+impl EstateTax {
+    /// Tax on an estate worth `estate`.
+    pub fn tax(&self, estate: f64) -> f64 {
+        self.rates.tax(estate - self.exemption)
+    }
+
+    /// Reports issues under `prefix`, e.g. `estate_tax.rates.flat`.
+    pub(crate) fn validate(&self, prefix: &str, issues: &mut Vec<ValidationIssue>) {
+        validate_exemption(self.exemption, prefix, issues);
+        self.rates.validate(&format!("{}.rates", prefix), issues);
+    }
+}
+
+impl InheritanceTax {
+    /// Tax on inheriting `amount` after `inherited` earlier in life.
+    pub fn tax(&self, inherited: f64, amount: f64) -> f64 {
+        let taxable = |total: f64| self.rates.tax(total - self.exemption);
+        taxable(inherited + amount) - taxable(inherited)
+    }
+
+    /// Reports issues under `prefix`, e.g. `inheritance_tax.exemption`.
+    pub(crate) fn validate(&self, prefix: &str, issues: &mut Vec<ValidationIssue>) {
+        validate_exemption(self.exemption, prefix, issues);
+        self.rates.validate(&format!("{}.rates", prefix), issues);
+    }
+}
+
+fn validate_exemption(exemption: f64, prefix: &str, issues: &mut Vec<ValidationIssue>) {
+    if !(exemption.is_finite() && exemption >= 0.0) {
+        issues.push(ValidationIssue {
+            path: format!("{}.exemption", prefix),
+            constraint: "must be at least 0".to_string(),
+        });
+    }
+}
+
+/// Tax on `amount` under marginal `brackets`; nothing for amounts below the
+/// first threshold.
+pub(crate) fn bracketed_tax(brackets: &[TaxBracket], amount: f64) -> f64 {
+    brackets
+        .iter()
+        .enumerate()
+        .map(|(i, bracket)| {
+            let upper = brackets.get(i + 1).map_or(f64::INFINITY, |b| b.threshold);
+            (amount.min(upper) - bracket.threshold).max(0.0) * bracket.rate
+        })
+        .sum()
+}
+
+/// Checks that `brackets` is non-empty, with rates in [0, 1] and thresholds
+/// that start at 0 or above and strictly increase.
+pub(crate) fn validate_brackets(
+    brackets: &[TaxBracket],
+    prefix: &str,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    if brackets.is_empty() {
+        issues.push(ValidationIssue {
+            path: prefix.to_string(),
+            constraint: "must contain at least one bracket".to_string(),
+        });
+    }
+    for (i, bracket) in brackets.iter().enumerate() {
+        let mut check = |ok: bool, field: &str, constraint: &str| {
+            if !ok {
+                issues.push(ValidationIssue {
+                    path: format!("{}.{}.{}", prefix, i, field),
+                    constraint: constraint.to_string(),
+                });
+            }
+        };
+        check(
+            bracket.threshold.is_finite() && bracket.threshold >= 0.0,
+            "threshold",
+            "must be at least 0",
+        );
+        check(
+            (0.0..=1.0).contains(&bracket.rate),
+            "rate",
+            "must be within [0, 1]",
+        );
+        if i > 0 {
+            check(
+                bracket.threshold > brackets[i - 1].threshold,
+                "threshold",
+                "must be greater than the threshold of the previous bracket",
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brackets(spec: &[(f64, f64)]) -> Vec<TaxBracket> {
+        spec.iter()
+            .map(|&(threshold, rate)| TaxBracket { threshold, rate })
+            .collect()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn brackets_tax_each_slice_at_its_marginal_rate() {
+        let b = brackets(&[(10.0, 0.1), (20.0, 0.2), (50.0, 0.5)]);
+        assert_eq!(bracketed_tax(&b, 5.0), 0.0);
+        assert_eq!(bracketed_tax(&b, 10.0), 0.0);
+        assert!(close(bracketed_tax(&b, 15.0), 0.5));
+        assert!(close(bracketed_tax(&b, 20.0), 1.0));
+        assert!(close(bracketed_tax(&b, 30.0), 1.0 + 2.0));
+        assert!(close(bracketed_tax(&b, 100.0), 1.0 + 6.0 + 25.0));
+        assert_eq!(bracketed_tax(&b, -10.0), 0.0);
+    }
+
+    #[test]
+    fn standard_deduction_is_subtracted_before_the_brackets() {
+        let tax = IncomeTax {
+            standard_deduction: 4.0,
+            brackets: brackets(&[(0.0, 0.1), (10.0, 0.3)]),
+        };
+        assert_eq!(tax.tax(3.0), 0.0);
+        assert_eq!(tax.tax(4.0), 0.0);
+        assert!(close(tax.tax(9.0), 0.5));
+        assert!(close(tax.tax(24.0), 1.0 + 3.0));
+    }
+
+    #[test]
+    fn flat_rates_apply_to_positive_amounts_only() {
+        assert!(close(TaxRates::Flat(0.25).tax(40.0), 10.0));
+        assert_eq!(TaxRates::Flat(0.25).tax(-40.0), 0.0);
+    }
+
+    #[test]
+    fn invalid_brackets_are_reported_by_index() {
+        let mut issues = Vec::new();
+        validate_brackets(
+            &brackets(&[(-1.0, 0.1), (5.0, 1.5), (5.0, 0.2)]),
+            "income_tax.brackets",
+            &mut issues,
+        );
+        let paths: Vec<&str> = issues.iter().map(|i| i.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "income_tax.brackets.0.threshold",
+                "income_tax.brackets.1.rate",
+                "income_tax.brackets.2.threshold",
+            ]
+        );
+
+        issues.clear();
+        validate_brackets(&[], "income_tax.brackets", &mut issues);
+        assert_eq!(issues.len(), 1);
+    }
+
+    #[test]
+    fn estate_tax_applies_above_the_exemption() {
+        let flat = EstateTax {
+            exemption: 100.0,
+            rates: TaxRates::Flat(0.2),
+        };
+        assert_eq!(flat.tax(80.0), 0.0);
+        assert!(close(flat.tax(300.0), 40.0));
+
+        let bracketed = EstateTax {
+            exemption: 100.0,
+            rates: TaxRates::Brackets(brackets(&[(0.0, 0.1), (100.0, 0.5)])),
+        };
+        assert!(close(bracketed.tax(300.0), 10.0 + 50.0));
+    }
+
+    #[test]
+    fn inheritance_tax_follows_the_lifetime_total() {
+        let tax = InheritanceTax {
+            exemption: 50.0,
+            rates: TaxRates::Brackets(brackets(&[(0.0, 0.1), (100.0, 0.3)])),
+        };
+        assert_eq!(tax.tax(0.0, 40.0), 0.0);
+        assert!(close(tax.tax(0.0, 130.0), 8.0));
+        // The same amount costs more once earlier inheritances used the exemption
+        // and the lower bracket
+        assert!(close(tax.tax(100.0, 130.0), 29.0));
+        // Splitting an inheritance does not lower the tax
+        assert!(close(
+            tax.tax(0.0, 100.0) + tax.tax(100.0, 130.0),
+            tax.tax(0.0, 230.0)
+        ));
+    }
+}
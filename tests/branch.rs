@@ -0,0 +1,62 @@
+mod common;
+
+use wealth_distribution_simulation::branch::Branch;
+use wealth_distribution_simulation::{ConfigError, Environment};
+
+fn apply(spec: &str) -> Result<Environment, ConfigError> {
+    let mut env = Environment::try_new(&common::small_config(2)).unwrap();
+    env.step().unwrap();
+    Branch::parse(spec)?.apply(&mut env)?;
+    Ok(env)
+}
+
+#[test]
+fn branches_change_fields_read_during_the_run() {
+    let env = apply("high_tax:transaction.tax_rate=0.2;num_iterations=80").unwrap();
+    assert_eq!(env.config().transaction.tax_rate, 0.2);
+    assert_eq!(env.config().num_iterations, 80);
+}
+
+#[test]
+fn branches_cannot_change_fields_read_at_start_up_or_creation() {
+    for spec in [
+        "a:num_agents=10",
+        "b:age_and_death.mid_age=40",
+        "c:education.children_education_jitter=1",
+        "d:wealth.max_initial_wealth=500",
+    ] {
+        match apply(spec) {
+            Err(ConfigError::Override { .. }) => {}
+            Err(e) => panic!("{}: unexpected error {}", spec, e),
+            Ok(_) => panic!("{}: was applied", spec),
+        }
+    }
+}
+
+#[test]
+fn branches_are_validated() {
+    assert!(matches!(
+        apply("bad:transaction.tax_rate=2"),
+        Err(ConfigError::Invalid(_))
+    ));
+}
+
+#[test]
+fn branches_cannot_schedule_changes_before_the_checkpoint() {
+    let entry = |at: usize| {
+        format!(
+            r#"s:schedule=[{{"path": "transaction.tax_rate", "at": {}, "value": 0.2}}]"#,
+            at
+        )
+    };
+    match apply(&entry(0)) {
+        Err(ConfigError::Invalid(issues)) => {
+            assert_eq!(issues.len(), 1);
+            assert_eq!(issues[0].path, "schedule.0.at");
+        }
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("a change before the checkpoint was applied"),
+    }
+    let env = apply(&entry(1)).unwrap();
+    assert_eq!(env.config().schedule.len(), 1);
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use wealth_distribution_simulation::{Environment, EnvironmentConfig, Metrics};
+
+fn run_to_end(config: &EnvironmentConfig, metrics: &Path) {
+    let mut env = Environment::try_new(config).unwrap();
+    env.add_sink(Box::new(Metrics::new(metrics.to_str().unwrap()).unwrap()));
+    env.run().unwrap();
+}
+
+#[test]
+fn resuming_a_checkpoint_reproduces_the_metrics_byte_for_byte() {
+    let dir = common::scratch_dir("checkpoint_resume");
+    let config = common::small_config(3);
+    let uninterrupted = dir.join("uninterrupted.csv");
+    run_to_end(&config, &uninterrupted);
+
+    // Crash five iterations after the checkpoint at iteration 10
+    let resumed = dir.join("resumed.csv");
+    let checkpoint = dir.join("run.checkpoint");
+    let mut env = Environment::try_new(&config).unwrap();
+    env.add_sink(Box::new(Metrics::new(resumed.to_str().unwrap()).unwrap()));
+    env.checkpoint_every(&checkpoint, 10);
+    while env.iteration() < 15 {
+        env.step().unwrap();
+    }
+    drop(env);
+
+    let mut env = Environment::load_checkpoint(&checkpoint).unwrap();
+    assert_eq!(env.iteration(), 10);
+    env.add_sink(Box::new(
+        Metrics::resume(resumed.to_str().unwrap(), env.iteration())
+            .unwrap()
+            .in_background(),
+    ));
+    env.run().unwrap();
+    drop(env);
+
+    assert_eq!(
+        fs::read(&uninterrupted).unwrap(),
+        fs::read(&resumed).unwrap()
+    );
+}
+
+#[test]
+fn checkpoints_keep_the_config() {
+    let dir = common::scratch_dir("checkpoint_config");
+    let checkpoint = dir.join("run.checkpoint");
+    let config = common::small_config(4);
+    let mut env = Environment::try_new(&config).unwrap();
+    env.step().unwrap();
+    env.save_checkpoint(&checkpoint).unwrap();
+
+    let restored = Environment::load_checkpoint(&checkpoint).unwrap();
+    assert_eq!(
+        serde_json::to_value(restored.config()).unwrap(),
+        serde_json::to_value(env.config()).unwrap()
+    );
+    assert_eq!(restored.agents().len(), env.agents().len());
+}
+
+#[test]
+fn files_that_are_not_checkpoints_are_rejected() {
+    let dir = common::scratch_dir("checkpoint_invalid");
+    let path = dir.join("not.checkpoint");
+    fs::write(&path, "iteration,gini\n").unwrap();
+    assert!(Environment::load_checkpoint(&path).is_err());
+}
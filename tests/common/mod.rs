@@ -0,0 +1,56 @@
+#![allow(dead_code)] // each test crate uses only some of the helpers
+
+use std::fs;
+use std::path::PathBuf;
+
+use wealth_distribution_simulation::government::{Government, Redistribution};
+use wealth_distribution_simulation::taxation::{
+    EstateTax, IncomeTax, InheritanceTax, TaxBracket, TaxRates,
+};
+use wealth_distribution_simulation::EnvironmentConfig;
+
+/// Fresh directory under the system temp dir, unique to the test binary and `name`.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("wds_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A seeded config small enough to run in a test, with every tax and the
+/// government enabled so that all of them take part in the run.
+pub fn small_config(seed: u64) -> EnvironmentConfig {
+    EnvironmentConfig {
+        seed: Some(seed),
+        num_iterations: 40,
+        num_agents: 400,
+        length: 100,
+        width: 100,
+        income_tax: Some(IncomeTax {
+            standard_deduction: 1.0,
+            brackets: vec![
+                TaxBracket {
+                    threshold: 0.0,
+                    rate: 0.1,
+                },
+                TaxBracket {
+                    threshold: 5.0,
+                    rate: 0.3,
+                },
+            ],
+        }),
+        estate_tax: Some(EstateTax {
+            exemption: 50.0,
+            rates: TaxRates::Flat(0.2),
+        }),
+        inheritance_tax: Some(InheritanceTax {
+            exemption: 10.0,
+            rates: TaxRates::Flat(0.1),
+        }),
+        government: Some(Government {
+            redistribution: Redistribution::MeansTested { bottom_share: 0.3 },
+            payout_rate: 0.5,
+        }),
+        ..EnvironmentConfig::default()
+    }
+}
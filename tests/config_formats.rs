@@ -0,0 +1,67 @@
+mod common;
+
+use serde_json::json;
+use wealth_distribution_simulation::EnvironmentConfig;
+
+/// A config using every kind of field: nested sections, optional sections,
+/// enums, lists of tables and a schedule.
+fn full_config() -> EnvironmentConfig {
+    let mut config = common::small_config(5);
+    config
+        .apply_assignment(
+            r#"convergence={"metrics": ["gini", "p50"], "criterion": "variance", "window": 8, "threshold": 0.001}"#,
+        )
+        .unwrap();
+    config
+        .apply_assignment(
+            r#"schedule=[{"path": "transaction.tax_rate", "at": 10, "until": 20, "value": 0.2}]"#,
+        )
+        .unwrap();
+    config.validate().unwrap();
+    config
+}
+
+fn round_trip(file_name: &str) {
+    let dir = common::scratch_dir(&format!("config_formats_{}", file_name));
+    let path = dir.join(file_name);
+    let config = full_config();
+    config.save_to_file(path.to_str().unwrap()).unwrap();
+
+    let loaded = EnvironmentConfig::load_from_file(path.to_str().unwrap()).unwrap();
+    assert_eq!(
+        serde_json::to_value(&loaded).unwrap(),
+        serde_json::to_value(&config).unwrap()
+    );
+}
+
+#[test]
+fn toml_configs_load_as_saved() {
+    round_trip("config.toml");
+}
+
+#[test]
+fn yaml_configs_load_as_saved() {
+    round_trip("config.yaml");
+    round_trip("config.yml");
+}
+
+#[test]
+fn unset_optional_fields_stay_unset() {
+    for file_name in ["config.toml", "config.yaml"] {
+        let dir = common::scratch_dir(&format!("config_formats_unset_{}", file_name));
+        let path = dir.join(file_name);
+        EnvironmentConfig::default()
+            .save_to_file(path.to_str().unwrap())
+            .unwrap();
+        let loaded = serde_json::to_value(
+            EnvironmentConfig::load_from_file(path.to_str().unwrap()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(loaded["seed"], json!(null), "{}", file_name);
+        assert_eq!(loaded["government"], json!(null), "{}", file_name);
+        assert_eq!(
+            loaded,
+            serde_json::to_value(EnvironmentConfig::default()).unwrap()
+        );
+    }
+}
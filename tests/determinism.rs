@@ -0,0 +1,73 @@
+mod common;
+
+use wealth_distribution_simulation::{Environment, EnvironmentConfig};
+
+/// Every logged column and the final wealth of every agent, as bits so that
+/// runs must agree exactly.
+fn fingerprint(config: &EnvironmentConfig, threads: usize) -> (Vec<u64>, Vec<u64>) {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+    pool.install(|| {
+        let mut env = Environment::try_new(config).unwrap();
+        let mut stats = Vec::new();
+        while !env.finished() {
+            env.step().unwrap();
+            let s = env.stats().unwrap();
+            stats.push(s.iteration as u64);
+            stats.extend(s.values().iter().map(|v| v.to_bits()));
+        }
+        let wealth = env.agents().iter().map(|a| a.wealth.to_bits()).collect();
+        (stats, wealth)
+    })
+}
+
+#[test]
+fn same_seed_gives_identical_runs() {
+    let config = common::small_config(7);
+    assert_eq!(fingerprint(&config, 2), fingerprint(&config, 2));
+}
+
+#[test]
+fn results_do_not_depend_on_the_number_of_threads() {
+    let config = common::small_config(11);
+    let single = fingerprint(&config, 1);
+    assert_eq!(single, fingerprint(&config, 8));
+    assert_eq!(single, fingerprint(&config, 3));
+}
+
+#[test]
+fn different_seeds_give_different_runs() {
+    assert_ne!(
+        fingerprint(&common::small_config(1), 2),
+        fingerprint(&common::small_config(2), 2)
+    );
+}
+
+#[test]
+fn metrics_files_are_identical_for_one_and_eight_jobs() {
+    let dir = common::scratch_dir("jobs");
+    let config_path = dir.join("config.json");
+    common::small_config(5)
+        .save_to_file(config_path.to_str().unwrap())
+        .unwrap();
+
+    let run = |jobs: &str| {
+        let output = dir.join(format!("metrics_j{}.csv", jobs));
+        let status =
+            std::process::Command::new(env!("CARGO_BIN_EXE_wealth-distribution-simulation"))
+                .arg("run")
+                .arg(&config_path)
+                .args(["-j", jobs, "-o"])
+                .arg(&output)
+                .stdout(std::process::Stdio::null())
+                .status()
+                .unwrap();
+        assert!(status.success());
+        std::fs::read(output).unwrap()
+    };
+    let single = run("1");
+    assert!(single.len() > 1000);
+    assert_eq!(single, run("8"));
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use std::fs;
+
+use wealth_distribution_simulation::{ConfigError, EnvironmentConfig};
+
+#[test]
+fn extending_files_override_the_base_field_by_field() {
+    let dir = common::scratch_dir("extends_merge");
+    let mut base = common::small_config(9);
+    base.transaction.amount_rate = 0.07;
+    base.save_to_file(dir.join("base.json").to_str().unwrap())
+        .unwrap();
+    fs::write(
+        dir.join("middle.toml"),
+        "extends = \"base.json\"\nnum_agents = 250\n\n[transaction]\ntax_rate = 0.2\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("child.yaml"),
+        "extends: middle.toml\ntransaction:\n  transaction_probability: 0.6\n",
+    )
+    .unwrap();
+
+    let config =
+        EnvironmentConfig::load_from_file(dir.join("child.yaml").to_str().unwrap()).unwrap();
+    assert_eq!(config.num_agents, 250);
+    assert_eq!(config.transaction.tax_rate, 0.2);
+    assert_eq!(config.transaction.transaction_probability, 0.6);
+    // Untouched fields of a section that is partly overridden come from the base
+    assert_eq!(config.transaction.amount_rate, 0.07);
+    assert_eq!(config.seed, Some(9));
+    assert!(config.estate_tax.is_some());
+}
+
+#[test]
+fn extends_paths_are_relative_to_the_extending_file() {
+    let dir = common::scratch_dir("extends_relative");
+    fs::create_dir_all(dir.join("variants")).unwrap();
+    common::small_config(1)
+        .save_to_file(dir.join("base.json").to_str().unwrap())
+        .unwrap();
+    fs::write(
+        dir.join("variants/high_tax.json"),
+        r#"{ "extends": "../base.json", "transaction": { "tax_rate": 0.3 } }"#,
+    )
+    .unwrap();
+
+    let config =
+        EnvironmentConfig::load_from_file(dir.join("variants/high_tax.json").to_str().unwrap())
+            .unwrap();
+    assert_eq!(config.transaction.tax_rate, 0.3);
+}
+
+#[test]
+fn extends_cycles_are_detected() {
+    let dir = common::scratch_dir("extends_cycle");
+    fs::write(dir.join("a.json"), r#"{ "extends": "b.json" }"#).unwrap();
+    fs::write(dir.join("b.json"), r#"{ "extends": "c.json" }"#).unwrap();
+    fs::write(dir.join("c.json"), r#"{ "extends": "a.json" }"#).unwrap();
+
+    match EnvironmentConfig::load_from_file(dir.join("a.json").to_str().unwrap()) {
+        Err(ConfigError::ExtendsCycle(chain)) => {
+            let names: Vec<&str> = chain
+                .iter()
+                .map(|p| p.rsplit(['/', '\\']).next().unwrap())
+                .collect();
+            assert_eq!(names, ["a.json", "b.json", "c.json", "a.json"]);
+        }
+        Err(e) => panic!("expected a cycle, got {}", e),
+        Ok(_) => panic!("expected a cycle, got a config"),
+    }
+}
+
+#[test]
+fn self_extension_is_a_cycle() {
+    let dir = common::scratch_dir("extends_self");
+    fs::write(dir.join("a.json"), r#"{ "extends": "./a.json" }"#).unwrap();
+    assert!(matches!(
+        EnvironmentConfig::load_from_file(dir.join("a.json").to_str().unwrap()),
+        Err(ConfigError::ExtendsCycle(_))
+    ));
+}
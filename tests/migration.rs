@@ -0,0 +1,134 @@
+mod common;
+
+use std::fs;
+
+use common::scratch_dir;
+use wealth_distribution_simulation::EnvironmentConfig;
+
+#[test]
+fn old_schema_json_is_migrated_and_renamed() {
+    let dir = scratch_dir("migration_json");
+    let path = dir.join("old.json");
+    fs::write(
+        &path,
+        r#"{ "income_and_consumption": { "aditional_consumption_rate": 0.25 } }"#,
+    )
+    .unwrap();
+
+    let warnings = EnvironmentConfig::migrate_file(path.to_str().unwrap())
+        .unwrap()
+        .unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("aditional_consumption_rate"));
+
+    let migrated: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(migrated["schema_version"], 2);
+    assert_eq!(
+        migrated["income_and_consumption"]["additional_consumption_rate"],
+        0.25
+    );
+    assert!(migrated["income_and_consumption"]
+        .get("aditional_consumption_rate")
+        .is_none());
+}
+
+#[test]
+fn old_schema_config_loads_with_the_renamed_field() {
+    let dir = scratch_dir("migration_load");
+    let mut config = serde_json::to_value(EnvironmentConfig::default()).unwrap();
+    let section = config["income_and_consumption"].as_object_mut().unwrap();
+    let rate = section.remove("additional_consumption_rate").unwrap();
+    section.insert("aditional_consumption_rate".to_string(), rate.clone());
+    config.as_object_mut().unwrap().remove("schema_version");
+    let path = dir.join("old.json");
+    fs::write(&path, config.to_string()).unwrap();
+
+    let loaded = EnvironmentConfig::load_from_file(path.to_str().unwrap()).unwrap();
+    assert_eq!(
+        serde_json::to_value(&loaded).unwrap()["income_and_consumption"]
+            ["additional_consumption_rate"],
+        rate
+    );
+}
+
+#[test]
+fn toml_and_yaml_keep_their_comments() {
+    let dir = scratch_dir("migration_comments");
+    let toml_path = dir.join("old.toml");
+    fs::write(
+        &toml_path,
+        "# Header\n[income_and_consumption]\n# rate comment\naditional_consumption_rate = 0.1 # inline\n",
+    )
+    .unwrap();
+    let yaml_path = dir.join("old.yaml");
+    fs::write(
+        &yaml_path,
+        "schema_version: 1  # old\nincome_and_consumption:\n  aditional_consumption_rate: 0.2 # inline\n",
+    )
+    .unwrap();
+
+    EnvironmentConfig::migrate_file(toml_path.to_str().unwrap()).unwrap();
+    EnvironmentConfig::migrate_file(yaml_path.to_str().unwrap()).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&toml_path).unwrap(),
+        "schema_version = 2\n# Header\n[income_and_consumption]\n# rate comment\nadditional_consumption_rate = 0.1 # inline\n"
+    );
+    assert_eq!(
+        fs::read_to_string(&yaml_path).unwrap(),
+        "schema_version: 2  # old\nincome_and_consumption:\n  additional_consumption_rate: 0.2 # inline\n"
+    );
+}
+
+#[test]
+fn files_that_cannot_be_edited_as_text_are_left_alone() {
+    let dir = scratch_dir("migration_refuse");
+    let path = dir.join("old.toml");
+    let content =
+        "note = \"aditional_consumption_rate = 1\"\n[income_and_consumption]\naditional_consumption_rate = 0.1\n";
+    fs::write(&path, content).unwrap();
+
+    let error = EnvironmentConfig::migrate_file(path.to_str().unwrap()).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("rename `aditional_consumption_rate` to `additional_consumption_rate`"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+}
+
+#[test]
+fn current_schema_files_are_not_touched() {
+    let dir = scratch_dir("migration_current");
+    let path = dir.join("current.json");
+    let content = "{\"schema_version\": 2, \"num_agents\": 10}";
+    fs::write(&path, content).unwrap();
+
+    assert!(EnvironmentConfig::migrate_file(path.to_str().unwrap())
+        .unwrap()
+        .is_none());
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+}
+
+#[test]
+fn loading_returns_the_warnings_of_every_file_in_the_chain() {
+    let dir = scratch_dir("migration_warnings");
+    let mut base = serde_json::to_value(EnvironmentConfig::default()).unwrap();
+    let section = base["income_and_consumption"].as_object_mut().unwrap();
+    let rate = section.remove("additional_consumption_rate").unwrap();
+    section.insert("aditional_consumption_rate".to_string(), rate.clone());
+    base.as_object_mut().unwrap().remove("schema_version");
+    fs::write(dir.join("base.json"), base.to_string()).unwrap();
+    let path = dir.join("child.json");
+    fs::write(&path, r#"{ "extends": "base.json", "schema_version": 2 }"#).unwrap();
+
+    let (config, warnings) =
+        EnvironmentConfig::read_from_file_with_warnings(path.to_str().unwrap()).unwrap();
+    assert_eq!(
+        serde_json::to_value(&config).unwrap()["income_and_consumption"]
+            ["additional_consumption_rate"],
+        rate
+    );
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].starts_with(&format!("{}: ", dir.join("base.json").display())));
+    assert!(warnings[0].contains("aditional_consumption_rate"));
+}
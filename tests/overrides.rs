@@ -0,0 +1,94 @@
+mod common;
+
+use wealth_distribution_simulation::convergence::ConvergenceCriterion;
+use wealth_distribution_simulation::government::Redistribution;
+use wealth_distribution_simulation::{ConfigError, EnvironmentConfig};
+
+fn assign(assignments: &[&str]) -> Result<EnvironmentConfig, ConfigError> {
+    let mut config = common::small_config(1);
+    for assignment in assignments {
+        config.apply_assignment(assignment)?;
+    }
+    Ok(config)
+}
+
+/// Path and message of the override error `assignment` fails with.
+fn override_error(assignment: &str) -> (String, String) {
+    match assign(&[assignment]) {
+        Err(ConfigError::Override { path, message }) => (path, message),
+        Err(e) => panic!("{}: unexpected error {}", assignment, e),
+        Ok(_) => panic!("{}: was applied", assignment),
+    }
+}
+
+#[test]
+fn nested_dotted_paths_reach_their_field() {
+    let config = assign(&[
+        "num_agents = 250",
+        "transaction.tax_rate=0.2",
+        "government.redistribution.bottom_share=0.5",
+    ])
+    .unwrap();
+    assert_eq!(config.num_agents, 250);
+    assert_eq!(config.transaction.tax_rate, 0.2);
+    assert_eq!(
+        config.government.unwrap().redistribution,
+        Redistribution::MeansTested { bottom_share: 0.5 }
+    );
+}
+
+#[test]
+fn values_are_read_as_json_and_otherwise_as_strings() {
+    let config = assign(&[
+        // Integers fill float fields
+        "transaction.tax_rate=1",
+        "seed=null",
+        "initial_population=agents.csv",
+        r#"convergence={"metrics": ["gini"], "criterion": "slope", "window": 5, "threshold": 0.01}"#,
+        "convergence.relative=true",
+    ])
+    .unwrap();
+    assert_eq!(config.transaction.tax_rate, 1.0);
+    assert_eq!(config.seed, None);
+    assert_eq!(config.initial_population.as_deref(), Some("agents.csv"));
+    let convergence = config.convergence.unwrap();
+    assert_eq!(convergence.criterion, ConvergenceCriterion::Slope);
+    assert_eq!(convergence.window, 5);
+    assert!(convergence.relative);
+
+    for assignment in [
+        "num_agents=2.5",
+        "num_agents=-5",
+        "num_agents=many",
+        "convergence.relative=yes",
+    ] {
+        let (path, _) = override_error(assignment);
+        assert_eq!(path, assignment.split('=').next().unwrap());
+    }
+}
+
+#[test]
+fn unknown_paths_are_rejected() {
+    for assignment in [
+        "transaction.tax=0.1",
+        "num_agents.value=1",
+        "convergence.window=5", // `convergence` is unset
+        "transaction..tax_rate=0.1",
+    ] {
+        let (path, message) = override_error(assignment);
+        assert_eq!(path, assignment.split('=').next().unwrap());
+        assert_eq!(message, "no such field");
+    }
+    let (_, message) = override_error("transaction=0.1");
+    assert!(message.contains("is a section"), "{}", message);
+}
+
+#[test]
+fn malformed_assignments_are_rejected() {
+    let (path, message) = override_error("num_agents");
+    assert_eq!(path, "num_agents");
+    assert_eq!(message, "expected <path>=<value>");
+    let (path, message) = override_error("=5");
+    assert_eq!(path, "");
+    assert_eq!(message, "no such field");
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use std::fs;
+
+use wealth_distribution_simulation::agent::AgentId;
+use wealth_distribution_simulation::population;
+use wealth_distribution_simulation::{ConfigError, Environment, EnvironmentConfig};
+
+const POPULATION: &str = "id,age,wealth,education,x,y,parents
+0,40,100.5,3,10,20,
+1,35,50.25,2,30,40,
+2,10,0,0.5,50,60,0;1
+3,5,1,0.25,70,80,1
+";
+
+#[test]
+fn populations_are_read_relative_to_the_config_and_round_trip_through_csv() {
+    let dir = common::scratch_dir("population_round_trip");
+    fs::create_dir_all(dir.join("data")).unwrap();
+    // Upper-case extensions are still read as CSV
+    fs::write(dir.join("data/agents.CSV"), POPULATION).unwrap();
+    let mut config = serde_json::to_value(common::small_config(4)).unwrap();
+    config["initial_population"] = "data/agents.CSV".into();
+    let config_path = dir.join("config.json");
+    fs::write(&config_path, config.to_string()).unwrap();
+
+    let config = EnvironmentConfig::load_from_file(config_path.to_str().unwrap()).unwrap();
+    let records = population::read(
+        dir.join("data/agents.CSV").as_path(),
+        &EnvironmentConfig::default(),
+    )
+    .unwrap();
+    let env = Environment::try_new(&config).unwrap();
+    assert_eq!(env.config().num_agents, 4);
+
+    // Parents get the agents that list them as children
+    let children = |id: usize| {
+        env.agents()
+            .iter()
+            .find(|a| a.id() == AgentId(id))
+            .unwrap()
+            .children
+            .clone()
+    };
+    assert_eq!(children(0), vec![AgentId(2)]);
+    assert_eq!(children(1), vec![AgentId(2), AgentId(3)]);
+    assert!(children(2).is_empty());
+
+    let written = dir.join("written.csv");
+    population::write_csv(&written, env.agents()).unwrap();
+    assert_eq!(population::read(&written, env.config()).unwrap(), records);
+}
+
+#[test]
+fn json_records_with_unknown_fields_are_rejected() {
+    let dir = common::scratch_dir("population_unknown_field");
+    let path = dir.join("agents.json");
+    fs::write(
+        &path,
+        r#"[
+            { "id": 0, "age": 30, "wealth": 1, "education": 1 },
+            { "id": 1, "age": 30, "wealth": 1, "education": 1, "parent": 0 }
+        ]"#,
+    )
+    .unwrap();
+    match population::read(&path, &EnvironmentConfig::default()) {
+        Err(ConfigError::Parse { message, .. }) => assert!(message.contains("parent")),
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("the unknown field was accepted"),
+    }
+}
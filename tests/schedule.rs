@@ -0,0 +1,89 @@
+mod common;
+
+use serde_json::json;
+use wealth_distribution_simulation::schedule::ScheduleEntry;
+use wealth_distribution_simulation::{ConfigError, Environment, EnvironmentConfig};
+
+fn entry(path: &str, at: usize, until: Option<usize>, value: serde_json::Value) -> ScheduleEntry {
+    ScheduleEntry {
+        path: path.to_string(),
+        at,
+        until,
+        value,
+    }
+}
+
+fn issues(config: &EnvironmentConfig) -> Vec<String> {
+    match config.validate() {
+        Err(ConfigError::Invalid(issues)) => issues.iter().map(ToString::to_string).collect(),
+        Err(e) => panic!("expected validation issues, got {}", e),
+        Ok(()) => Vec::new(),
+    }
+}
+
+#[test]
+fn entries_that_conflict_in_combination_are_rejected() {
+    let mut config = common::small_config(1);
+    config.education.learning_rate_min = 0.005;
+    config.education.learning_rate_max = 0.05;
+    config.schedule = vec![
+        entry("education.learning_rate_max", 5, None, json!(0.01)),
+        entry("education.learning_rate_min", 10, None, json!(0.02)),
+    ];
+    let issues = issues(&config);
+    assert_eq!(issues.len(), 1, "{:?}", issues);
+    assert!(issues[0].starts_with("schedule: "), "{}", issues[0]);
+    assert!(issues[0].contains("before iteration 10"), "{}", issues[0]);
+
+    // Raising the maximum first makes the same change valid
+    config.schedule[0].value = json!(0.03);
+    assert!(self::issues(&config).is_empty());
+}
+
+#[test]
+fn fields_read_only_when_agents_are_created_cannot_be_scheduled() {
+    for path in [
+        "age_and_death.mid_age",
+        "education.initial_adult_max",
+        "wealth.min_initial_wealth",
+    ] {
+        let mut config = common::small_config(1);
+        config.schedule = vec![entry(path, 5, None, json!(1.0))];
+        let issues = issues(&config);
+        assert_eq!(issues.len(), 1, "{:?}", issues);
+        assert!(
+            issues[0].contains("only read when agents are created"),
+            "{}",
+            issues[0]
+        );
+    }
+}
+
+#[test]
+fn steps_and_ramps_change_the_config_during_the_run() {
+    let mut config = common::small_config(1);
+    config.transaction.tax_rate = 0.0;
+    config.income_and_consumption.base_consumption = 10.0;
+    config.schedule = vec![
+        entry("transaction.tax_rate", 3, None, json!(0.2)),
+        entry(
+            "income_and_consumption.base_consumption",
+            2,
+            Some(6),
+            json!(14.0),
+        ),
+    ];
+    let mut env = Environment::try_new(&config).unwrap();
+    let mut tax_rates = Vec::new();
+    let mut consumption = Vec::new();
+    for _ in 0..8 {
+        env.step().unwrap();
+        tax_rates.push(env.config().transaction.tax_rate);
+        consumption.push(env.config().income_and_consumption.base_consumption);
+    }
+    assert_eq!(tax_rates, [0.0, 0.0, 0.0, 0.2, 0.2, 0.2, 0.2, 0.2]);
+    assert_eq!(
+        consumption,
+        [10.0, 10.0, 10.0, 11.0, 12.0, 13.0, 14.0, 14.0]
+    );
+}
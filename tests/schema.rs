@@ -0,0 +1,157 @@
+mod common;
+
+use serde_json::{json, Value};
+use wealth_distribution_simulation::EnvironmentConfig;
+
+/// Checks `value` against `schema`, resolving `$ref`s in `root`, and returns the
+/// paths that break it. Covers the keywords the generated schema uses.
+fn violations(root: &Value, schema: &Value, value: &Value, path: &str) -> Vec<String> {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.trim_start_matches("#/$defs/");
+        return violations(root, &root["$defs"][name], value, path);
+    }
+    let mut found = Vec::new();
+    let mut fail = |keyword: &str| found.push(format!("{}: {}", path, keyword));
+
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            other => other.as_str().into_iter().collect(),
+        };
+        let matches = |t: &str| match t {
+            "null" => value.is_null(),
+            "boolean" => value.is_boolean(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "string" => value.is_string(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            _ => false,
+        };
+        if !types.into_iter().any(matches) {
+            fail("type");
+        }
+    }
+    if schema.get("const").is_some_and(|c| c != value) {
+        fail("const");
+    }
+    if let Some(x) = value.as_f64() {
+        if schema["minimum"].as_f64().is_some_and(|min| x < min) {
+            fail("minimum");
+        }
+        if schema["maximum"].as_f64().is_some_and(|max| x > max) {
+            fail("maximum");
+        }
+        if schema["exclusiveMinimum"]
+            .as_f64()
+            .is_some_and(|min| x <= min)
+        {
+            fail("exclusiveMinimum");
+        }
+    }
+    if let Some(items) = value.as_array() {
+        if schema["minItems"]
+            .as_u64()
+            .is_some_and(|min| (items.len() as u64) < min)
+        {
+            fail("minItems");
+        }
+    }
+    if let (Some(object), Some(properties)) = (value.as_object(), schema["properties"].as_object())
+    {
+        for required in schema["required"].as_array().into_iter().flatten() {
+            if !object.contains_key(required.as_str().unwrap()) {
+                fail("required");
+            }
+        }
+        if schema["additionalProperties"] == json!(false)
+            && object.keys().any(|key| !properties.contains_key(key))
+        {
+            fail("additionalProperties");
+        }
+    }
+    for (keyword, needed) in [("oneOf", Some(1)), ("anyOf", None)] {
+        if let Some(options) = schema[keyword].as_array() {
+            let passing = options
+                .iter()
+                .filter(|option| violations(root, option, value, path).is_empty())
+                .count();
+            if needed.map_or(passing == 0, |needed| passing != needed) {
+                fail(keyword);
+            }
+        }
+    }
+
+    if let (Some(object), Some(properties)) = (value.as_object(), schema["properties"].as_object())
+    {
+        for (key, field) in object {
+            if let Some(field_schema) = properties.get(key) {
+                found.extend(violations(
+                    root,
+                    field_schema,
+                    field,
+                    &format!("{}.{}", path, key),
+                ));
+            }
+        }
+    }
+    if let (Some(items), Some(item_schema)) = (value.as_array(), schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            found.extend(violations(
+                root,
+                item_schema,
+                item,
+                &format!("{}.{}", path, i),
+            ));
+        }
+    }
+    found
+}
+
+fn check(config: &Value) -> Vec<String> {
+    let schema = EnvironmentConfig::json_schema();
+    violations(&schema, &schema, config, "config")
+}
+
+#[test]
+fn default_and_example_configs_validate_against_the_schema() {
+    let default = serde_json::to_value(EnvironmentConfig::default()).unwrap();
+    assert_eq!(check(&default), Vec::<String>::new());
+
+    let mut full = common::small_config(3);
+    full.apply_assignment(
+        r#"convergence={"metrics": ["gini"], "criterion": "slope", "window": 5, "threshold": 0.01}"#,
+    )
+    .unwrap();
+    full.apply_assignment(
+        r#"schedule=[{"path": "transaction.tax_rate", "at": 10, "until": 20, "value": 0.2}]"#,
+    )
+    .unwrap();
+    full.validate().unwrap();
+    assert_eq!(
+        check(&serde_json::to_value(full).unwrap()),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn the_schema_only_accepts_the_current_schema_version() {
+    let mut config = serde_json::to_value(EnvironmentConfig::default()).unwrap();
+    assert_eq!(
+        EnvironmentConfig::json_schema()["properties"]["schema_version"]["const"],
+        json!(2)
+    );
+    config["schema_version"] = json!(1);
+    assert_eq!(check(&config), vec!["config.schema_version: const"]);
+
+    config["schema_version"] = json!(2);
+    config["num_iterations"] = json!(0);
+    config["transaction"]["tax_rate"] = json!(1.5);
+    assert_eq!(
+        check(&config),
+        vec![
+            "config.num_iterations: minimum",
+            "config.transaction.tax_rate: maximum"
+        ]
+    );
+}
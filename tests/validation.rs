@@ -0,0 +1,75 @@
+mod common;
+
+use wealth_distribution_simulation::government::{Government, Redistribution};
+use wealth_distribution_simulation::taxation::{IncomeTax, TaxBracket};
+use wealth_distribution_simulation::{ConfigError, EnvironmentConfig};
+
+fn issue_paths(config: &EnvironmentConfig) -> Vec<String> {
+    match config.validate() {
+        Err(ConfigError::Invalid(issues)) => issues.into_iter().map(|i| i.path).collect(),
+        other => panic!("expected validation issues, got {:?}", other),
+    }
+}
+
+#[test]
+fn valid_configs_pass() {
+    EnvironmentConfig::default().validate().unwrap();
+    common::small_config(1).validate().unwrap();
+}
+
+#[test]
+fn every_issue_is_reported_with_its_path() {
+    let mut config = common::small_config(1);
+    config.num_agents = 0;
+    config.transaction.tax_rate = 1.5;
+    config.education.learning_rate_min = 0.5;
+    config.education.learning_rate_max = 0.1;
+    config.income_tax = Some(IncomeTax {
+        standard_deduction: -1.0,
+        brackets: vec![
+            TaxBracket {
+                threshold: 10.0,
+                rate: 0.1,
+            },
+            TaxBracket {
+                threshold: 5.0,
+                rate: 1.2,
+            },
+        ],
+    });
+    config.government = Some(Government {
+        redistribution: Redistribution::MeansTested { bottom_share: 0.0 },
+        payout_rate: 2.0,
+    });
+
+    assert_eq!(
+        issue_paths(&config),
+        [
+            "num_agents",
+            "education.learning_rate_min",
+            "transaction.tax_rate",
+            "income_tax.standard_deduction",
+            "income_tax.brackets.1.rate",
+            "income_tax.brackets.1.threshold",
+            "government.payout_rate",
+            "government.redistribution.bottom_share",
+        ]
+    );
+}
+
+#[test]
+fn loading_an_invalid_file_reports_its_issues() {
+    let dir = common::scratch_dir("validation_file");
+    let path = dir.join("invalid.json");
+    let mut config = common::small_config(1);
+    config.transaction.tax_rate = -0.5;
+    config.wealth.min_initial_wealth = 200.0;
+    config.save_to_file(path.to_str().unwrap()).unwrap();
+
+    let Err(error) = EnvironmentConfig::load_from_file(path.to_str().unwrap()) else {
+        panic!("invalid config was loaded");
+    };
+    let message = error.to_string();
+    assert!(message.contains("transaction.tax_rate"), "{}", message);
+    assert!(message.contains("wealth.min_initial_wealth"), "{}", message);
+}